@@ -0,0 +1,627 @@
+use std::fmt::Display;
+use std::str::FromStr;
+use super::tiles::*;
+use super::meld::*;
+use super::rules::RuleSet;
+use super::solve::*;
+use super::notation::{Letters, Notation};
+
+/// A board as it is currently laid out on the table: an ordered list
+/// of melds. Melds are numbered from 1 when shown to players.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Board {
+    pub melds: Vec<Meld>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum BoardError {
+    Tiles(TilesError),
+    /// The given tiles do not form a single valid meld
+    InvalidMeld(String),
+}
+
+impl Display for BoardError {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            BoardError::Tiles(e) => e.fmt(fmt),
+            BoardError::InvalidMeld(s) => write!(fmt, "\"{}\" is not a valid meld", s),
+        }
+    }
+}
+
+impl std::error::Error for BoardError {}
+
+impl From<TilesError> for BoardError {
+    fn from(e: TilesError) -> BoardError {
+        BoardError::Tiles(e)
+    }
+}
+
+impl Board {
+    pub fn new() -> Board {
+        Board { melds: vec![] }
+    }
+
+    /// Parse melds separated by `|` with the tiles written in the
+    /// given notation, each following `rules`. Jokers in a run stand
+    /// in for the tile at their place, see `Meld::classify_layout`.
+    pub fn parse_with(s: &str, notation: &dyn Notation, rules: &RuleSet) -> Result<Board, BoardError> {
+        let mut board = Board::new();
+        if s.trim().is_empty() {
+            return Ok(board);
+        }
+        let mut tiles = Tiles::new();
+        let mut offset = 0;
+        let mut index = 0;
+        for part in s.split('|') {
+            // Errors give the position in the whole board
+            let layout = notation.parse_layout(part).map_err(|e| e.shifted(offset, index))?;
+            // No more than two of a tile across all of the melds, with
+            // the position of the meld which has one too many
+            let start = offset + part.len() - part.trim_start().len();
+            for (i, tile) in layout.iter().enumerate() {
+                tiles.add_tile(tile).map_err(|e| e.shifted(start, index + i))?;
+            }
+            offset += part.len() + 1;
+            index += layout.len();
+            match Meld::classify_layout(&layout, rules).into_iter().next() {
+                None => return Err(BoardError::InvalidMeld(String::from(part.trim()))),
+                Some(meld) => board.melds.push(meld),
+            }
+        }
+        Ok(board)
+    }
+
+    /// All of the physical tiles on the board, or an error if the
+    /// melds have more than two of a tile between them
+    pub fn tiles(&self) -> Result<Tiles, TilesError> {
+        let mut tiles = Tiles::new();
+        for meld in &self.melds {
+            for (tile, count) in meld.tiles().iter() {
+                for _ in 0..count {
+                    tiles.add_tile(&tile)?;
+                }
+            }
+        }
+        Ok(tiles)
+    }
+
+    /// Work out how to get from this layout to the one in `target`,
+    /// moving as few tiles as possible. `rack` holds the tiles which
+    /// may be played from the player's hand. Returns `None` if
+    /// `target` does not place every tile on this board, or needs
+    /// tiles which are neither on the board nor in the rack.
+    ///
+    /// Each meld in `target` is built from the existing meld it has
+    /// the most tiles in common with, using the Hungarian algorithm
+    /// to find the pairing which leaves the most tiles where they
+    /// are.
+    pub fn diff(&self, target: &Solution, rack: Tiles) -> Option<Rearrangement> {
+        let current: Vec<Tiles> = self.melds.iter().map(Meld::tiles).collect();
+        let wanted: Vec<Tiles> = target.combos.iter().map(Meld::tiles).collect();
+
+        let weights: Vec<Vec<u32>> = current
+            .iter()
+            .map(|from| wanted.iter().map(|to| overlap(from, to)).collect())
+            .collect();
+        let pairs = max_weight_matching(&weights, wanted.len());
+
+        let mut origins = vec![None; wanted.len()];
+        for (i, pair) in pairs.iter().enumerate() {
+            if let Some(j) = pair {
+                origins[*j] = Some(i);
+            }
+        }
+
+        // Tiles which have to leave each existing meld
+        let mut surplus: Vec<Tiles> = current
+            .iter()
+            .zip(&pairs)
+            .map(|(from, pair)| match pair {
+                None => *from,
+                Some(j) => minus(from, &wanted[*j]),
+            })
+            .collect();
+        let mut rack = rack;
+
+        // Group the tiles being moved by where they come from and go
+        let mut moves: Vec<(Option<usize>, Destination, Tiles)> = vec![];
+        let mut add_move = |from: Option<usize>, to: Destination, tile: &Tile| {
+            match moves.iter_mut().find(|(f, t, _)| *f == from && *t == to) {
+                Some((_, _, tiles)) => tiles.add_tile(tile).unwrap(),
+                None => {
+                    let mut tiles = Tiles::new();
+                    tiles.add_tile(tile).unwrap();
+                    moves.push((from, to, tiles));
+                }
+            }
+        };
+
+        let mut new_melds = 0;
+        let mut destinations = vec![];
+        for (j, to) in wanted.iter().enumerate() {
+            let (needed, destination) = match origins[j] {
+                Some(i) => (minus(to, &current[i]), Destination::Meld(i)),
+                None => {
+                    new_melds += 1;
+                    (*to, Destination::New(new_melds - 1))
+                }
+            };
+            destinations.push(destination);
+            for tile in Tile::all() {
+                for _ in 0..needed.get_count(&tile) {
+                    let from = take(&mut surplus, &mut rack, &tile)?;
+                    add_move(from, destination, &tile);
+                }
+            }
+        }
+        for _ in 0..target.leftover_jokers {
+            let joker = Tile::jokers().find(|joker| surplus.iter().any(|tiles| tiles.get_count(joker) > 0))?;
+            let from = take(&mut surplus, &mut Tiles::new(), &joker)?;
+            add_move(from, Destination::Aside, &joker);
+        }
+
+        // Every tile from the board has to end up somewhere
+        if surplus.iter().any(|tiles| !tiles.is_empty()) {
+            return None;
+        }
+
+        // Rearrange the board before playing from the rack
+        moves.sort_by_key(|(from, _, _)| from.is_none());
+
+        // A new meld made entirely of tiles from one existing meld is
+        // split off from it
+        let is_split = |to: &Destination, tiles: &Tiles| match destinations.iter().position(|d| d == to) {
+            Some(j) => wanted[j] == *tiles,
+            None => false,
+        };
+        let steps = moves
+            .iter()
+            .map(|(from, to, tiles)| {
+                let tiles = *tiles;
+                match (from, to) {
+                    (None, to) => Step::Play { tiles, to: *to },
+                    (Some(from), Destination::Aside) => Step::SetAside { tiles, from: *from },
+                    (Some(from), Destination::New(new)) if is_split(to, &tiles) => Step::Split {
+                        tiles,
+                        from: *from,
+                        new: *new,
+                    },
+                    (Some(from), to) => Step::Move { tiles, from: *from, to: *to },
+                }
+            })
+            .collect();
+
+        Some(Rearrangement { steps, destinations })
+    }
+
+    /// How many melds on this board are left intact by `solution`,
+    /// that is, appear in it with exactly the same physical tiles.
+    /// Jokers may stand in for different tiles in an intact meld.
+    pub fn stability(&self, solution: &Solution) -> usize {
+        let mut wanted: Vec<Tiles> = solution.combos.iter().map(Meld::tiles).collect();
+        let mut kept = 0;
+        for meld in &self.melds {
+            let tiles = meld.tiles();
+            if let Some(j) = wanted.iter().position(|to| *to == tiles) {
+                wanted.remove(j);
+                kept += 1;
+            }
+        }
+        kept
+    }
+
+    /// Sort solutions so that those which disturb this board the
+    /// least come first: the most melds left intact, and then the
+    /// fewest tiles moved. Solutions which `diff` can't reach from
+    /// this board go last. Otherwise the order is unchanged.
+    pub fn sort_by_stability(&self, solutions: Vec<Solution>, rack: Tiles) -> Vec<Solution> {
+        let mut keyed: Vec<_> = solutions
+            .into_iter()
+            .map(|solution| {
+                let moved = self
+                    .diff(&solution, rack)
+                    .map(|rearrangement| std::cmp::Reverse(rearrangement.tiles_moved()));
+                let key = (self.stability(&solution), moved.is_some(), moved);
+                (std::cmp::Reverse(key), solution)
+            })
+            .collect();
+        keyed.sort_by_key(|(key, _)| *key);
+        keyed.into_iter().map(|(_, solution)| solution).collect()
+    }
+}
+
+/// Take a tile from the first existing meld which has it spare, or
+/// otherwise from the rack, returning `Some(None)` for the rack.
+/// Returns `None` if the tile isn't available anywhere.
+fn take(surplus: &mut [Tiles], rack: &mut Tiles, tile: &Tile) -> Option<Option<usize>> {
+    match surplus.iter().position(|tiles| tiles.get_count(tile) > 0) {
+        Some(i) => {
+            surplus[i].set_count(tile, surplus[i].get_count(tile) - 1);
+            Some(Some(i))
+        }
+        None if rack.get_count(tile) > 0 => {
+            rack.set_count(tile, rack.get_count(tile) - 1);
+            Some(None)
+        }
+        None => None,
+    }
+}
+
+/// The tiles in `x` which are not in `y`
+fn minus(x: &Tiles, y: &Tiles) -> Tiles {
+    x.difference(y)
+}
+
+/// The number of tiles `x` and `y` have in common
+fn overlap(x: &Tiles, y: &Tiles) -> u32 {
+    u32::from(x.intersection(y).get_total_count())
+}
+
+/// Pair up rows with columns to get the highest total weight, using
+/// the Hungarian algorithm. Returns the column paired with each row,
+/// leaving out pairs with no weight.
+fn max_weight_matching(weights: &[Vec<u32>], cols: usize) -> Vec<Option<usize>> {
+    const INFINITY: i64 = 1 << 62;
+    let rows = weights.len();
+    let n = rows.max(cols);
+    let max = weights.iter().flat_map(|row| row.iter()).cloned().max().unwrap_or(0);
+    // Turn it into minimizing costs on a square matrix
+    let cost = |i: usize, j: usize| -> i64 {
+        if i < rows && j < cols {
+            i64::from(max - weights[i][j])
+        } else {
+            i64::from(max)
+        }
+    };
+
+    // Potentials for rows and columns, and the row paired with each
+    // column, all indexed from 1 with 0 as a sentinel.
+    let mut u = vec![0i64; n + 1];
+    let mut v = vec![0i64; n + 1];
+    let mut pair = vec![0usize; n + 1];
+    let mut way = vec![0usize; n + 1];
+    for i in 1..=n {
+        pair[0] = i;
+        let mut j0 = 0;
+        let mut minv = vec![INFINITY; n + 1];
+        let mut used = vec![false; n + 1];
+        loop {
+            used[j0] = true;
+            let i0 = pair[j0];
+            let mut delta = INFINITY;
+            let mut j1 = 0;
+            for j in 1..=n {
+                if !used[j] {
+                    let cur = cost(i0 - 1, j - 1) - u[i0] - v[j];
+                    if cur < minv[j] {
+                        minv[j] = cur;
+                        way[j] = j0;
+                    }
+                    if minv[j] < delta {
+                        delta = minv[j];
+                        j1 = j;
+                    }
+                }
+            }
+            for j in 0..=n {
+                if used[j] {
+                    u[pair[j]] += delta;
+                    v[j] -= delta;
+                } else {
+                    minv[j] -= delta;
+                }
+            }
+            j0 = j1;
+            if pair[j0] == 0 {
+                break;
+            }
+        }
+        loop {
+            let j1 = way[j0];
+            pair[j0] = pair[j1];
+            j0 = j1;
+            if j0 == 0 {
+                break;
+            }
+        }
+    }
+
+    let mut res = vec![None; rows];
+    for j in 1..=n {
+        let i = pair[j];
+        if i >= 1 && i <= rows && j <= cols && weights[i - 1][j - 1] > 0 {
+            res[i - 1] = Some(j - 1);
+        }
+    }
+    res
+}
+
+/// Where tiles are moved to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Destination {
+    /// An existing meld, by its index on the current board
+    Meld(usize),
+    /// A meld which has to be started, numbered in the order they
+    /// are needed
+    New(usize),
+    /// Leftover jokers, which are taken off the board
+    Aside,
+}
+
+impl Display for Destination {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Destination::Meld(i) => write!(fmt, "meld {}", i + 1),
+            Destination::New(i) => write!(fmt, "new meld {}", i + 1),
+            Destination::Aside => write!(fmt, "the side"),
+        }
+    }
+}
+
+/// A single physical action in a `Rearrangement`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Step {
+    /// Split tiles off an existing meld to make a new meld on their
+    /// own
+    Split { tiles: Tiles, from: usize, new: usize },
+    /// Move tiles from one meld to another
+    Move { tiles: Tiles, from: usize, to: Destination },
+    /// Play tiles from the rack
+    Play { tiles: Tiles, to: Destination },
+    /// Take leftover jokers off the board
+    SetAside { tiles: Tiles, from: usize },
+}
+
+impl Step {
+    /// The tiles which are moved in this step
+    pub fn tiles(&self) -> Tiles {
+        match self {
+            Step::Split { tiles, .. } => *tiles,
+            Step::Move { tiles, .. } => *tiles,
+            Step::Play { tiles, .. } => *tiles,
+            Step::SetAside { tiles, .. } => *tiles,
+        }
+    }
+}
+
+impl Display for Step {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Step::Split { tiles, from, new } => {
+                write!(fmt, "Split {} off meld {} into new meld {}", tiles, from + 1, new + 1)
+            }
+            Step::Move { tiles, from, to } => write!(fmt, "Move {} from meld {} to {}", tiles, from + 1, to),
+            Step::Play { tiles, to } => write!(fmt, "Play {} from the rack to {}", tiles, to),
+            Step::SetAside { tiles, from } => write!(fmt, "Take {} from meld {} and set it aside", tiles, from + 1),
+        }
+    }
+}
+
+/// The steps to rearrange a board into a new layout, see
+/// `Board::diff`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rearrangement {
+    pub steps: Vec<Step>,
+    /// Where each combo of the target ends up: either an existing
+    /// meld which is added to and taken from, or a new meld
+    pub destinations: Vec<Destination>,
+}
+
+impl Rearrangement {
+    /// The total number of tiles which have to be moved
+    pub fn tiles_moved(&self) -> u8 {
+        self.steps.iter().map(|step| step.tiles().get_total_count()).sum()
+    }
+}
+
+impl Display for Rearrangement {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        if self.steps.is_empty() {
+            return write!(fmt, "Nothing to move");
+        }
+        for (i, step) in self.steps.iter().enumerate() {
+            if i > 0 {
+                writeln!(fmt)?;
+            }
+            write!(fmt, "{}. {}", i + 1, step)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Board {
+    type Err = BoardError;
+
+    /// Parse melds separated by `|`, such as `1R 2R 3R | 5B 5U 5O`
+    fn from_str(s: &str) -> Result<Board, BoardError> {
+        Board::parse_with(s, &Letters::standard(), &RuleSet::standard())
+    }
+}
+
+impl Display for Board {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        for (i, meld) in self.melds.iter().enumerate() {
+            if i > 0 {
+                write!(fmt, " | ")?;
+            }
+            write!(fmt, "{}", meld)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn target(board: &str) -> Solution {
+        Solution {
+            combos: board.parse::<Board>().unwrap().melds,
+            leftover_jokers: 0,
+        }
+    }
+
+    fn diff_helper(from: &str, to: &str, rack: &str) -> Vec<String> {
+        let board: Board = from.parse().unwrap();
+        let res = board.diff(&target(to), rack.parse().unwrap()).unwrap();
+        res.steps.iter().map(Step::to_string).collect()
+    }
+
+    #[test]
+    fn test_parse_board() {
+        let board: Board = "1R 2R 3R | 5B 5U 5O".parse().unwrap();
+        assert_eq!(board.melds.len(), 2);
+        assert_eq!(board.to_string(), "1R 2R 3R | 5B 5U 5O");
+        assert_eq!(board.tiles().unwrap(), "1R 2R 3R 5B 5U 5O".parse().unwrap());
+        assert_eq!(board.to_string().parse::<Board>(), Ok(board));
+        assert_eq!("".parse::<Board>(), Ok(Board::new()));
+    }
+
+    #[test]
+    fn test_parse_joker_position() {
+        let board: Board = "J 6R 7R | 5U J 7U".parse().unwrap();
+        assert_eq!(board.to_string(), "J=5R 6R 7R | 5U J=6U 7U");
+        assert_eq!(board.tiles().unwrap(), "6R 7R 5U 7U J J".parse().unwrap());
+        let board: Board = "6B 7B J | 8O J 8B".parse().unwrap();
+        assert_eq!(board.to_string(), "6B 7B J=8B | 8B J=8U 8O");
+        assert_eq!(
+            "6R J 7R".parse::<Board>(),
+            Err(BoardError::InvalidMeld(String::from("6R J 7R")))
+        );
+    }
+
+    #[test]
+    fn test_parse_with_rules() {
+        assert_eq!(
+            "12R 13R 1R".parse::<Board>(),
+            Err(BoardError::InvalidMeld(String::from("12R 13R 1R")))
+        );
+        let mut rules = RuleSet::standard();
+        rules.set("wrap-around=true").unwrap();
+        let board = Board::parse_with("12R 13R 1R | 5B 5U 5O", &Letters::standard(), &rules).unwrap();
+        assert_eq!(board.to_string(), "12R 13R 1R | 5B 5U 5O");
+        rules.set("min-meld-size=4").unwrap();
+        assert!(Board::parse_with("12R 13R 1R", &Letters::standard(), &rules).is_err());
+    }
+
+    #[test]
+    fn test_parse_too_many_copies() {
+        let e = match "1R 2R 3R | 1R 2R 3R | 1R 2R 3R".parse::<Board>() {
+            Err(BoardError::Tiles(e)) => e,
+            res => panic!("Unexpected {:?}", res),
+        };
+        assert_eq!(e.inner(), &TilesError::AlreadyHaveTwo("1R".parse().unwrap()));
+        assert_eq!(e.position(), Some((22, 6)));
+        assert!("J 2R 3R | 5B J 5O | 7O 8O J".parse::<Board>().is_err());
+
+        let mut board: Board = "1R 2R 3R | 1R 2R 3R".parse().unwrap();
+        board.melds.push(board.melds[0].clone());
+        assert_eq!(board.tiles(), Err(TilesError::AlreadyHaveTwo("1R".parse().unwrap())));
+    }
+
+    #[test]
+    fn test_parse_invalid_meld() {
+        assert_eq!(
+            "1R 2R 3R | 5B 7O".parse::<Board>(),
+            Err(BoardError::InvalidMeld(String::from("5B 7O")))
+        );
+        assert_eq!(
+            "1R 2R 3R 4R 5R 6R".parse::<Board>().map(|board| board.melds.len()),
+            Ok(1)
+        );
+    }
+
+    #[test]
+    fn test_parse_error_position() {
+        let e = match "1R 2R 3R | 5B 5U 5Q".parse::<Board>() {
+            Err(BoardError::Tiles(e)) => e,
+            res => panic!("Unexpected {:?}", res),
+        };
+        assert_eq!(e.position(), Some((17, 5)));
+        assert_eq!(e.to_string(), "Invalid tile \"5Q\" at position 6 (byte 17), did you mean 5O?");
+    }
+
+    #[test]
+    fn test_diff_unchanged() {
+        assert!(diff_helper("1R 2R 3R | 5B 5U 5O", "5B 5U 5O | 1R 2R 3R", "").is_empty());
+    }
+
+    #[test]
+    fn test_diff_play_from_rack() {
+        assert_eq!(
+            diff_helper("1R 2R 3R | 5B 5U 5O", "1R 2R 3R 4R | 5B 5U 5O 5R | 9O 10O 11O", "4R 5R 9O 10O 11O 13B"),
+            vec![
+                "Play 4R from the rack to meld 1",
+                "Play 5R from the rack to meld 2",
+                "Play 9O 10O 11O from the rack to new meld 1",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_diff_move() {
+        assert_eq!(
+            diff_helper("1R 2R 3R 4R | 4B 4U 4O", "1R 2R 3R | 4B 4U 4O 4R", ""),
+            vec!["Move 4R from meld 1 to meld 2"]
+        );
+    }
+
+    #[test]
+    fn test_diff_split() {
+        let board: Board = "1R 2R 3R 4R 5R 6R 7R".parse().unwrap();
+        let res = board.diff(&target("1R 2R 3R 4R | 5R 6R 7R"), Tiles::new()).unwrap();
+        assert_eq!(res.tiles_moved(), 3);
+        assert_eq!(res.to_string(), "1. Split 5R 6R 7R off meld 1 into new meld 1");
+    }
+
+    #[test]
+    fn test_diff_rebuild() {
+        // Two runs turn into three groups, keeping three tiles in
+        // place and moving the other six
+        let board: Board = "1R 2R 3R | 1O 2O 3O | 1B 2B 3B".parse().unwrap();
+        let res = board.diff(&target("1B 1O 1R | 2B 2O 2R | 3B 3O 3R"), Tiles::new()).unwrap();
+        assert_eq!(res.tiles_moved(), 6);
+        assert!(res.steps.iter().all(|step| step.to_string().starts_with("Move")));
+    }
+
+    #[test]
+    fn test_diff_jokers() {
+        let board: Board = "1R J 3R".parse().unwrap();
+        let target = Solution {
+            combos: "1R 2R 3R".parse::<Board>().unwrap().melds,
+            leftover_jokers: 1,
+        };
+        let res = board.diff(&target, "2R".parse().unwrap()).unwrap();
+        assert_eq!(res.to_string(), "1. Take J from meld 1 and set it aside\n2. Play 2R from the rack to meld 1");
+    }
+
+    #[test]
+    fn test_stability() {
+        let board: Board = "1R 2R 3R | 5B 5U 5O | 1R J 3R".parse().unwrap();
+        assert_eq!(board.stability(&target("5B 5U 5O | 1R 2R 3R | 1R J 3R")), 3);
+        assert_eq!(board.stability(&target("5B 5U 5O 5R | 1R 2R 3R | 1R J 3R")), 2);
+        assert_eq!(board.stability(&target("1R 2R 3R 4R | 5B 5U 5O 5R | 1R 2R 3R J")), 0);
+    }
+
+    #[test]
+    fn test_sort_by_stability() {
+        let board: Board = "1R 2R 3R 4R 5R 6R | 4B 4U 4O".parse().unwrap();
+        let solutions = vec![
+            target("1R 2R 3R | 4R 4B 4U 4O | 5R 6R 7R"),
+            target("1R 2R 3R 4R 5R 6R 7R | 4B 4U 4O"),
+            target("1R 2R 3R 4R | 5R 6R 7R | 4B 4U 4O"),
+        ];
+        let solutions = board.sort_by_stability(solutions, "7R".parse().unwrap());
+        let kept: Vec<usize> = solutions.iter().map(|solution| board.stability(solution)).collect();
+        assert_eq!(kept, vec![1, 1, 0]);
+        assert_eq!(solutions[0], target("1R 2R 3R 4R 5R 6R 7R | 4B 4U 4O"));
+    }
+
+    #[test]
+    fn test_diff_invalid_target() {
+        let board: Board = "1R 2R 3R | 5B 5U 5O".parse().unwrap();
+        assert_eq!(board.diff(&target("1R 2R 3R"), Tiles::new()), None);
+        assert_eq!(board.diff(&target("1R 2R 3R 4R | 5B 5U 5O"), Tiles::new()), None);
+    }
+}
@@ -1,14 +1,329 @@
-use super::tiles::{Tiles, TilesError};
-use super::solve::solve;
+use super::tiles::{Tile, Tiles, TilesError};
+use super::board::{Board, BoardError};
+use super::rules::RuleError;
+use super::notation::{notation, Letters, Notation};
+use super::solve::{
+    solve_opening_with, unplaceable_tiles_with_options, Objective, RackResult, RackSolution, SolveOptions, StopReason,
+    OPENING_THRESHOLD,
+};
+use super::solver::{solver, solvers, Solver};
+use std::collections::HashSet;
+use std::time::{Duration, Instant};
 
-pub fn main() -> Result<(), TilesError> {
-    for arg in std::env::args().skip(1) {
-        let tiles = arg.parse::<Tiles>()?;
-        println!("Trying to solve board: {}", tiles);
-        for solution in solve(tiles) {
-            println!("Solution: {}", solution);
+#[derive(Debug)]
+pub enum CliError {
+    Tiles(TilesError),
+    Board(BoardError),
+    Rule(RuleError),
+    MissingArgument(String),
+    InvalidObjective(String),
+    InvalidNumber(String),
+    InvalidNotation(String),
+    InvalidSolver(String),
+}
+
+impl std::fmt::Display for CliError {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            CliError::Tiles(e) => write!(fmt, "{}", e),
+            CliError::Board(e) => write!(fmt, "{}", e),
+            CliError::Rule(e) => write!(fmt, "{}", e),
+            CliError::MissingArgument(arg) => write!(fmt, "Missing argument for {}", arg),
+            CliError::InvalidObjective(s) => {
+                write!(fmt, "Invalid objective {}, expected count, points or high", s)
+            }
+            CliError::InvalidNumber(s) => write!(fmt, "Invalid number {}", s),
+            CliError::InvalidNotation(s) => write!(
+                fmt,
+                "Invalid notation {}, expected standard, color-first, german, yellow, long, long-rank-first or emoji",
+                s
+            ),
+            CliError::InvalidSolver(s) => {
+                let names: Vec<&str> = solvers().iter().map(|solver| solver.name()).collect();
+                write!(fmt, "Invalid solver {}, expected {}", s, names.join(" or "))
+            }
+        }
+    }
+}
+
+impl From<TilesError> for CliError {
+    fn from(e: TilesError) -> CliError {
+        CliError::Tiles(e)
+    }
+}
+
+impl From<BoardError> for CliError {
+    fn from(e: BoardError) -> CliError {
+        CliError::Board(e)
+    }
+}
+
+impl From<RuleError> for CliError {
+    fn from(e: RuleError) -> CliError {
+        CliError::Rule(e)
+    }
+}
+
+fn parse_objective(s: &str) -> Result<Objective, CliError> {
+    match s {
+        "count" => Ok(Objective::TileCount),
+        "points" => Ok(Objective::PointSum),
+        "high" => Ok(Objective::HighTiles),
+        _ => Err(CliError::InvalidObjective(String::from(s))),
+    }
+}
+
+fn parse_notation(s: &str) -> Result<Box<dyn Notation>, CliError> {
+    match notation(s) {
+        Some(notation) => Ok(notation),
+        None => Err(CliError::InvalidNotation(String::from(s))),
+    }
+}
+
+fn parse_solver(s: &str) -> Result<Box<dyn Solver>, CliError> {
+    match solver(s) {
+        Some(solver) => Ok(solver),
+        None => Err(CliError::InvalidSolver(String::from(s))),
+    }
+}
+
+fn next_value(args: &mut impl Iterator<Item = String>, arg: &str) -> Result<String, CliError> {
+    match args.next() {
+        None => Err(CliError::MissingArgument(String::from(arg))),
+        Some(value) => Ok(value),
+    }
+}
+
+fn parse_number<T: std::str::FromStr>(value: String) -> Result<T, CliError> {
+    match value.parse() {
+        Ok(n) => Ok(n),
+        Err(_) => Err(CliError::InvalidNumber(value)),
+    }
+}
+
+/// Print the solutions for a board, or only the distinct ones,
+/// returning whether there were none at all.
+fn print_solutions(tiles: Tiles, options: &SolveOptions, solver: &dyn Solver, notation: &dyn Notation, distinct: bool) -> bool {
+    let mut found = false;
+    let stopped = if distinct {
+        let result = solver.solve_distinct(tiles, options);
+        for solution in &result.solutions {
+            println!("Solution: {}", notation.format_solution(solution));
+            found = true;
+        }
+        result.stopped
+    } else {
+        solver.solve_each(tiles, options, &mut |solution| {
+            println!("Solution: {}", notation.format_solution(&solution));
+            found = true;
+        })
+    };
+    report_stopped(stopped, found)
+}
+
+/// The best plays from the rack, leaving out the repeats in canonical
+/// form like `Solver::solve_distinct` if `distinct` is set
+fn solve_rack(
+    solver: &dyn Solver,
+    tiles: Tiles,
+    rack: Tiles,
+    objective: Objective,
+    options: &SolveOptions,
+    distinct: bool,
+) -> RackResult {
+    let mut result = solver.solve_rack(tiles, rack, objective, options);
+    if distinct {
+        let mut seen = HashSet::new();
+        result.solutions = result
+            .solutions
+            .into_iter()
+            .map(|solution| RackSolution { solution: solution.solution.canonical(), ..solution })
+            .filter(|solution| seen.insert(solution.solution.clone()))
+            .collect();
+    }
+    result
+}
+
+/// Say why a search stopped early, if it did, returning whether the
+/// board turned out to have no solutions at all.
+fn report_stopped(stopped: Option<StopReason>, found: bool) -> bool {
+    match stopped {
+        Some(reason) => {
+            println!("Search stopped early: {}", reason);
+            false
+        }
+        None => !found,
+    }
+}
+
+/// The options for searching one board, with the deadline `timeout`
+/// from now
+fn with_timeout(options: &SolveOptions, timeout: Option<Duration>) -> SolveOptions {
+    SolveOptions {
+        deadline: timeout.map(|timeout| Instant::now() + timeout),
+        ..options.clone()
+    }
+}
+
+/// List tiles in a sentence, like "7O, 9O and 12B"
+fn describe_tiles(tiles: &Tiles, notation: &dyn Notation) -> String {
+    let mut names = vec![];
+    for tile in Tile::all() {
+        for _ in 0..tiles.get_count(&tile) {
+            names.push(notation.format_tile(&tile));
+        }
+    }
+    match names.pop() {
+        None => String::new(),
+        Some(last) => {
+            if names.is_empty() {
+                last
+            } else {
+                format!("{} and {}", names.join(", "), last)
+            }
+        }
+    }
+}
+
+/// Solve each board given on the command line. The `--rack TILES`,
+/// `--objective count|points|high` and `--threshold POINTS` arguments
+/// apply to all of the boards which follow them, as does
+/// `--distinct`, which skips solutions that only differ in the order
+/// of their combos or what the jokers stand in for, with or without
+/// a rack or layout. `--opening TILES` looks for an initial meld from
+/// the given rack.
+///
+/// `--layout MELDS` takes a board as it is laid out, with melds
+/// separated by `|`, and shows how to rearrange it into each
+/// solution, listing the solutions which keep the most melds intact
+/// first.
+///
+/// When a board has no solutions at all, the smallest set of tiles
+/// which would have to be taken off it is reported instead.
+///
+/// Searches can be given limits with `--max-solutions N`,
+/// `--max-nodes N` and `--timeout SECONDS`, and played with house
+/// rules with `--rule NAME=VALUE`, see `RuleSet::set`. The limits and
+/// house rules, including the rules for special jokers `J1` to `J3`,
+/// apply to boards with or without a rack, layouts, openings and the
+/// search for tiles which cannot be placed. The timeout starts again
+/// for each argument. `--max-solutions` counts the arrangements
+/// looked at, so with a rack or an opening it can cut off better
+/// plays.
+///
+/// `--notation NAME` reads and writes the tiles of the arguments which
+/// follow it in another notation, such as `long` for `red-7` or
+/// `german` for the German color letters, see `notation::notation`.
+///
+/// `--solver NAME` picks the algorithm used for the boards which
+/// follow it, see `solver::solver`. The default is `parallel` when
+/// built with that feature, and `backtracking` otherwise.
+pub fn main() -> Result<(), CliError> {
+    let mut rack = None;
+    let mut objective = Objective::TileCount;
+    let mut threshold = OPENING_THRESHOLD;
+    let mut distinct = false;
+    let mut options = SolveOptions::default();
+    let mut timeout = None;
+    let mut notation: Box<dyn Notation> = Box::new(Letters::standard());
+    let mut solver = solvers().remove(0);
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--rack" => rack = Some(notation.parse_tiles(&next_value(&mut args, &arg)?)?),
+            "--objective" => objective = parse_objective(&next_value(&mut args, &arg)?)?,
+            "--distinct" => distinct = true,
+            "--threshold" => threshold = parse_number(next_value(&mut args, &arg)?)?,
+            "--max-solutions" => options.max_solutions = Some(parse_number(next_value(&mut args, &arg)?)?),
+            "--rule" => options.rules.set(&next_value(&mut args, &arg)?)?,
+            "--max-nodes" => options.max_nodes = Some(parse_number(next_value(&mut args, &arg)?)?),
+            "--notation" => notation = parse_notation(&next_value(&mut args, &arg)?)?,
+            "--solver" => solver = parse_solver(&next_value(&mut args, &arg)?)?,
+            "--timeout" => {
+                timeout = Some(Duration::from_secs(parse_number(next_value(&mut args, &arg)?)?))
+            }
+            "--opening" => {
+                let rack = notation.parse_tiles(&next_value(&mut args, &arg)?)?;
+                println!(
+                    "Looking for an opening of at least {} from rack: {}",
+                    threshold,
+                    notation.format_tiles(&rack)
+                );
+                let result = solve_opening_with(rack, threshold, &with_timeout(&options, timeout));
+                match result.openings {
+                    Some(openings) => {
+                        println!("Highest: {}", notation.format_rack_solution(&openings.highest));
+                        println!("Fewest tiles: {}", notation.format_rack_solution(&openings.fewest_tiles));
+                    }
+                    None if result.stopped.is_some() => (),
+                    None => println!("No opening found"),
+                }
+                if let Some(reason) = result.stopped {
+                    println!("Search stopped early: {}", reason);
+                }
+                println!("* * *");
+            }
+            "--layout" => {
+                let board = Board::parse_with(&next_value(&mut args, &arg)?, &*notation, &options.rules)?;
+                println!("Rearranging board: {}", notation.format_board(&board));
+                let tiles = board.tiles()?;
+                let options = with_timeout(&options, timeout);
+                let (solutions, stopped): (Vec<_>, _) = match rack {
+                    None => {
+                        let result = if distinct {
+                            solver.solve_distinct(tiles, &options)
+                        } else {
+                            solver.solve(tiles, &options)
+                        };
+                        (result.solutions, result.stopped)
+                    }
+                    Some(rack) => {
+                        println!("Playing from rack: {}", notation.format_tiles(&rack));
+                        let result = solve_rack(&*solver, tiles, rack, objective, &options, distinct);
+                        (result.solutions.into_iter().map(|solution| solution.solution).collect(), result.stopped)
+                    }
+                };
+                let rack = rack.unwrap_or_default();
+                for solution in board.sort_by_stability(solutions, rack) {
+                    println!(
+                        "Solution keeping {} of {} melds: {}",
+                        board.stability(&solution),
+                        board.melds.len(),
+                        notation.format_solution(&solution)
+                    );
+                    if let Some(rearrangement) = board.diff(&solution, rack) {
+                        println!("{}", rearrangement);
+                    }
+                }
+                if let Some(reason) = stopped {
+                    println!("Search stopped early: {}", reason);
+                }
+                println!("* * *");
+            }
+            _ => {
+                let tiles = notation.parse_tiles(&arg)?;
+                println!("Trying to solve board: {}", notation.format_tiles(&tiles));
+                let options = with_timeout(&options, timeout);
+                let unsolvable = match rack {
+                    None => print_solutions(tiles, &options, &*solver, &*notation, distinct),
+                    Some(rack) => {
+                        println!("Playing from rack: {}", notation.format_tiles(&rack));
+                        let result = solve_rack(&*solver, tiles, rack, objective, &options, distinct);
+                        for solution in &result.solutions {
+                            println!("Solution: {}", notation.format_rack_solution(solution));
+                        }
+                        report_stopped(result.stopped, !result.solutions.is_empty())
+                    }
+                };
+                if unsolvable {
+                    match unplaceable_tiles_with_options(tiles, &options) {
+                        Ok(tiles) => println!("No solutions: {} cannot be placed", describe_tiles(&tiles, &*notation)),
+                        Err(reason) => println!("No solutions, and finding the tiles which cannot be placed stopped early: {}", reason),
+                    }
+                }
+                println!("* * *");
+            }
         }
-        println!("* * *");
     }
 
     Ok(())
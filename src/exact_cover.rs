@@ -0,0 +1,440 @@
+use std::collections::{HashMap, HashSet};
+use super::tiles::*;
+use super::meld::*;
+use super::rules::RuleSet;
+use super::solve::*;
+use super::solver::{Backtracking, Solver};
+use super::table::MeldTable;
+
+/// Solving a board as an exact cover problem: the melds chosen have
+/// to cover every tile on the board exactly as many times as there
+/// are copies of it. This is Knuth's Algorithm X, with items which
+/// are covered more than once as in his Algorithm M, choosing the
+/// melds from a `MeldTable`. Rather than dancing links, the tiles
+/// which are left are a `Tiles`, so covering a meld is a single
+/// subtraction, and checking that every tile can still be covered
+/// cuts off dead ends long before the search in `solve` finds them.
+///
+/// To find the same solutions as `solve`, the tiles are covered
+/// lowest first, and a joker never stands in for a tile which is
+/// still left to be placed.
+///
+/// Jokers don't need to be covered, and any left over are reported
+/// in `Solution::leftover_jokers`. Special jokers aren't in the
+/// table, so boards and racks with special jokers are passed on to
+/// `Backtracking`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ExactCover;
+
+impl Solver for ExactCover {
+    fn name(&self) -> &'static str {
+        "exact-cover"
+    }
+
+    fn solve_each(&self, board: Tiles, options: &SolveOptions, found: &mut dyn FnMut(Solution)) -> Option<StopReason> {
+        if has_special_jokers(&board) {
+            return Backtracking.solve_each(board, options, found);
+        }
+        let built;
+        let table = if options.rules == RuleSet::standard() {
+            MeldTable::standard()
+        } else {
+            built = MeldTable::new(&options.rules);
+            &built
+        };
+        let melds = Melds::new(table, &options.rules);
+        let mut cover = Cover::new(&melds, options);
+        let mut need = board;
+        need.set_count(&Tile::Joker, 0);
+        cover.search(board, need, &mut |chosen, left| {
+            found(Solution {
+                combos: chosen.iter().map(|(meld, _)| (*meld).clone()).collect(),
+                leftover_jokers: left.get_joker_count(),
+            })
+        });
+        cover.stopped
+    }
+
+    fn solve_rack(&self, board: Tiles, rack: Tiles, objective: Objective, options: &SolveOptions) -> RackResult {
+        let all = match board.checked_add(&rack) {
+            Some(all) if !has_special_jokers(&all) => all,
+            _ => return Backtracking.solve_rack(board, rack, objective, options),
+        };
+        let rules = &options.rules;
+        let built;
+        let table = if *rules == RuleSet::standard() {
+            MeldTable::standard()
+        } else {
+            built = MeldTable::new(rules);
+            &built
+        };
+        let melds = Melds::new(table, rules);
+        let mut cover = Cover::new(&melds, options);
+        let mut need = board;
+        need.set_count(&Tile::Joker, 0);
+
+        let mut res: Vec<RackSolution> = vec![];
+        let mut best = None;
+        cover.search(all, need, &mut |chosen, _| {
+            // Tiles, including jokers, are taken from the board first
+            let used = chosen.iter().fold(Tiles::new(), |used, (_, tiles)| used.checked_add(tiles).unwrap());
+            let played = used.difference(&board);
+            let leftover_jokers = board.get_joker_count() - (used.get_joker_count() - played.get_joker_count());
+            let score = objective.score_with(&played, rules);
+
+            let key = (std::cmp::Reverse(leftover_jokers), score);
+            match best {
+                Some(best) if key < best => return,
+                Some(best) if key == best => (),
+                _ => {
+                    best = Some(key);
+                    res.clear();
+                }
+            }
+            res.push(RackSolution {
+                solution: Solution {
+                    combos: chosen.iter().map(|(meld, _)| (*meld).clone()).collect(),
+                    leftover_jokers,
+                },
+                played,
+                remaining: rack.checked_sub(&played).unwrap(),
+                score,
+            });
+        });
+        RackResult {
+            solutions: res,
+            stopped: cover.stopped,
+        }
+    }
+}
+
+fn has_special_jokers(tiles: &Tiles) -> bool {
+    tiles.get_joker_count() > tiles.get_count(&Tile::Joker)
+}
+
+/// The melds to choose from, with each set of tiles which makes a
+/// meld as one option, along with the ways of laying it out.
+struct Melds<'a> {
+    rules: &'a RuleSet,
+    options: Vec<(Tiles, Vec<&'a Meld>)>,
+    /// The options using each tile, in order, by `Tile::index`
+    by_tile: Vec<Vec<usize>>,
+    /// The options using each tile which don't have another of those
+    /// options inside them. If any option with a tile can be made,
+    /// one of these can.
+    smallest: Vec<Vec<usize>>,
+    /// The most copies of each tile a single meld can use
+    most: Tiles,
+}
+
+impl<'a> Melds<'a> {
+    fn new(table: &'a MeldTable, rules: &'a RuleSet) -> Melds<'a> {
+        let mut options: Vec<(Tiles, Vec<&Meld>)> = vec![];
+        let mut seen = HashMap::new();
+        for entry in table.entries() {
+            let i = *seen.entry(entry.tiles).or_insert_with(|| {
+                options.push((entry.tiles, vec![]));
+                options.len() - 1
+            });
+            options[i].1.push(&entry.meld);
+        }
+        let mut by_tile = vec![vec![]; 64];
+        for (i, (tiles, _)) in options.iter().enumerate() {
+            for (tile, _) in tiles.iter() {
+                by_tile[usize::from(tile.index() / 2)].push(i);
+            }
+        }
+        let smallest = by_tile
+            .iter()
+            .map(|with_tile| {
+                // Anything inside a larger option is inside one of the
+                // smallest, so only those need to be compared against
+                let mut by_size = with_tile.clone();
+                by_size.sort_by_key(|i| options[*i].0.get_total_count());
+                let mut smallest: Vec<usize> = vec![];
+                for i in by_size {
+                    if !smallest.iter().any(|j| options[*j].0.is_subset(&options[i].0)) {
+                        smallest.push(i);
+                    }
+                }
+                smallest
+            })
+            .collect();
+        let copies = if rules.duplicate_group_colors { 2 } else { 1 };
+        let mut most = Tiles::new();
+        for tile in Tile::all_no_jokers() {
+            most.set_count(&tile, copies);
+        }
+        Melds { rules, options, by_tile, smallest, most }
+    }
+
+    fn containing(&self, tile: &Tile) -> &[usize] {
+        &self.by_tile[usize::from(tile.index() / 2)]
+    }
+
+    /// A way of laying out the given option which the search in
+    /// `solve` would find, when `rank` is the rank of the lowest tile
+    /// left, and `left` are the tiles left once the option is placed.
+    /// That search only tries runs which start from the lowest tile,
+    /// or which only start lower so that they can reach 13, and takes
+    /// every natural tile it can before using jokers.
+    fn layout(&self, option: usize, rank: u8, left: &Tiles) -> Option<&'a Meld> {
+        let rules = self.rules;
+        self.options[option].1.iter().cloned().find(|meld| {
+            let tried = match meld.kind {
+                MeldKind::Run { start, len, .. } if start < rank => !rules.wrap_around && start + len - 1 == 13,
+                MeldKind::Run { start, .. } if start > rank => {
+                    rules.wrap_around && meld.layout().first().map(|(_, is_joker)| !is_joker) == Some(true)
+                }
+                MeldKind::Run { .. } | MeldKind::Group { .. } => true,
+            };
+            tried && left.intersection(&meld.jokers).is_empty()
+        })
+    }
+}
+
+/// The melds chosen so far, with their tiles
+type Chosen<'a> = Vec<(&'a Meld, Tiles)>;
+
+/// The search for covers
+struct Cover<'a> {
+    melds: &'a Melds<'a>,
+    options: &'a SolveOptions,
+    chosen: Chosen<'a>,
+    /// The tiles which were available, and still needed, when the
+    /// search found no solutions at all
+    dead: HashSet<(Tiles, Tiles)>,
+    nodes: u64,
+    found: u64,
+    stopped: Option<StopReason>,
+}
+
+impl<'a> Cover<'a> {
+    fn new(melds: &'a Melds<'a>, options: &'a SolveOptions) -> Cover<'a> {
+        Cover {
+            melds,
+            options,
+            chosen: vec![],
+            dead: HashSet::new(),
+            nodes: 0,
+            found: 0,
+            stopped: None,
+        }
+    }
+
+    /// Pass every way of covering `need`, using only the tiles in
+    /// `available`, to `emit` along with the tiles left over. The
+    /// other available tiles may be left uncovered. Returns `false`
+    /// once a limit is reached.
+    fn search(&mut self, available: Tiles, need: Tiles, emit: &mut dyn FnMut(&Chosen<'a>, Tiles)) -> bool {
+        let found = self.found;
+        let carry_on = self.cover(available, need, emit);
+        if carry_on && self.found == found {
+            self.dead.insert((available, need));
+        }
+        carry_on
+    }
+
+    fn cover(&mut self, available: Tiles, need: Tiles, emit: &mut dyn FnMut(&Chosen<'a>, Tiles)) -> bool {
+        self.nodes += 1;
+        if let Some(max) = self.options.max_solutions {
+            if self.found >= max as u64 {
+                self.stopped = Some(StopReason::MaxSolutions);
+                return false;
+            }
+        }
+        if let Some(reason) = self.options.check_limits(self.nodes) {
+            self.stopped = Some(reason);
+            return false;
+        }
+        if self.dead.contains(&(available, need)) || self.dead_end(&available, &need) {
+            return true;
+        }
+        let (lowest, rank) = match available.iter().next() {
+            Some((Tile::Number(rank, color), _)) => (Tile::Number(rank, color), rank),
+            _ => {
+                self.found += 1;
+                emit(&self.chosen, available);
+                return true;
+            }
+        };
+
+        // Place one meld with this tile, or both copies in two melds.
+        // Any copies which aren't placed are kept in hand.
+        let melds = self.melds;
+        // Only the options which could still be laid out after one
+        // more meld has been placed are worth trying
+        let candidates: Vec<usize> = melds
+            .containing(&lowest)
+            .iter()
+            .cloned()
+            .filter(|&option| match available.checked_sub(&melds.options[option].0) {
+                Some(left) => melds.layout(option, rank, &left.difference(&melds.most)).is_some(),
+                None => false,
+            })
+            .collect();
+        for &first in &candidates {
+            let first_tiles = melds.options[first].0;
+            let after_first = match available.checked_sub(&first_tiles) {
+                Some(after) => after,
+                None => continue,
+            };
+            let first_meld = match melds.layout(first, rank, &after_first) {
+                Some(meld) if after_first.get_count(&lowest) > 0 => Some(meld),
+                _ => None,
+            };
+            if let Some(first_meld) = first_meld {
+                for &second in &candidates {
+                    let second_tiles = melds.options[second].0;
+                    let after_both = match after_first.checked_sub(&second_tiles) {
+                        Some(after) => after,
+                        None => continue,
+                    };
+                    let second_meld = match melds.layout(second, rank, &after_both) {
+                        Some(meld) => meld,
+                        None => continue,
+                    };
+                    // When the melds could go in either order, only
+                    // place them in one of them
+                    if second < first
+                        && melds.layout(first, rank, &after_both).is_some()
+                        && melds.layout(second, rank, &available.checked_sub(&second_tiles).unwrap()).is_some()
+                    {
+                        continue;
+                    }
+                    let need = need.difference(&first_tiles).difference(&second_tiles);
+                    if !self.place(&[(first_meld, first_tiles), (second_meld, second_tiles)], after_both, need, emit) {
+                        return false;
+                    }
+                }
+            }
+            let need = need.difference(&first_tiles);
+            if need.get_count(&lowest) > 0 {
+                continue;
+            }
+            let mut left = after_first;
+            left.set_count(&lowest, 0);
+            if let Some(meld) = melds.layout(first, rank, &left) {
+                if !self.place(&[(meld, first_tiles)], left, need, emit) {
+                    return false;
+                }
+            }
+        }
+        if need.get_count(&lowest) == 0 {
+            let mut left = available;
+            left.set_count(&lowest, 0);
+            return self.search(left, need, emit);
+        }
+        true
+    }
+
+    fn place(&mut self, melds: &[(&'a Meld, Tiles)], available: Tiles, need: Tiles, emit: &mut dyn FnMut(&Chosen<'a>, Tiles)) -> bool {
+        self.chosen.extend_from_slice(melds);
+        let carry_on = self.search(available, need, emit);
+        let len = self.chosen.len() - melds.len();
+        self.chosen.truncate(len);
+        carry_on
+    }
+
+    /// Is there a tile which has to be covered, but which no meld
+    /// made from the available tiles covers?
+    fn dead_end(&self, available: &Tiles, need: &Tiles) -> bool {
+        let melds = self.melds;
+        need.iter().any(|(tile, _)| {
+            !melds.smallest[usize::from(tile.index() / 2)]
+                .iter()
+                .any(|i| melds.options[*i].0.is_subset(available))
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::collections::HashSet;
+    use crate::test::Bencher;
+    use super::super::solve::test::full_deck;
+
+    /// The full deck without the jokers, 12R or the other 13s, so
+    /// that 13R can't be placed. A search which starts from the
+    /// lowest tiles only finds out at the very end.
+    fn unsolvable_deck() -> Tiles {
+        let mut tiles = full_deck();
+        for tile in &["J", "12R", "13B", "13U", "13O"] {
+            tiles.set_count(&tile.parse().unwrap(), 0);
+        }
+        tiles
+    }
+
+    #[test]
+    fn test_full_deck() {
+        let options = SolveOptions { max_solutions: Some(1000), ..SolveOptions::default() };
+        let result = ExactCover.solve(full_deck(), &options);
+        assert_eq!(result.solutions.len(), 1000);
+        let mut seen = HashSet::new();
+        for solution in &result.solutions {
+            assert_eq!(solution.validate(), Ok(()));
+            let placed: u8 = solution.combos.iter().map(|meld| meld.tiles().get_total_count()).sum();
+            assert_eq!(placed + solution.leftover_jokers, 106);
+            // Each set of melds is only found once
+            let mut combos = solution.combos.clone();
+            combos.sort();
+            assert!(seen.insert(combos));
+        }
+
+        assert!(ExactCover.solve(unsolvable_deck(), &SolveOptions::default()).solutions.is_empty());
+    }
+
+    #[test]
+    fn test_matches_solve() {
+        // Every solution from the search in `solve` is found, with no
+        // repeats beyond what `solve_distinct` removes
+        for s in &[
+            "1R 2R 3R 4R 5R 6R 1B 1U 1O J J 7R 8R",
+            "1R 1R 2R 2R 3R 3R 4R J J",
+            "10R 11R 12R 13R 10R 11R 12R 13R 13B 13U 13O J",
+        ] {
+            let tiles: Tiles = s.parse().unwrap();
+            let solutions = ExactCover.solve(tiles, &SolveOptions::default()).solutions;
+            let canonical: HashSet<Solution> = solutions.iter().map(Solution::canonical).collect();
+            let expected: HashSet<Solution> = solve_distinct(tiles).into_iter().collect();
+            assert_eq!(canonical, expected, "{}", s);
+            assert!(solutions.len() <= solve(tiles).len());
+        }
+    }
+
+    #[test]
+    fn test_special_jokers() {
+        let mut rules = RuleSet::standard();
+        rules.set("joker1=runs").unwrap();
+        let options = SolveOptions { rules, ..SolveOptions::default() };
+        let tiles: Tiles = "5B 5U 5O J1 6R 7R".parse().unwrap();
+        let expected = Backtracking.solve(tiles, &options).solutions;
+        assert!(!expected.is_empty());
+        assert_eq!(ExactCover.solve(tiles, &options).solutions, expected);
+    }
+
+    #[bench]
+    fn bench_full_deck_exact_cover(b: &mut Bencher) {
+        let options = SolveOptions { max_solutions: Some(10000), ..SolveOptions::default() };
+        b.iter(|| ExactCover.solve(full_deck(), &options).solutions.len())
+    }
+
+    #[bench]
+    fn bench_full_deck_search(b: &mut Bencher) {
+        b.iter(|| solve_iter(full_deck()).take(10000).count())
+    }
+
+    #[bench]
+    fn bench_unsolvable_deck_exact_cover(b: &mut Bencher) {
+        b.iter(|| ExactCover.solve(unsolvable_deck(), &SolveOptions::default()).solutions.len())
+    }
+
+    #[bench]
+    fn bench_unsolvable_deck_search(b: &mut Bencher) {
+        // Proving there are no solutions takes minutes, so this only
+        // times the first nodes
+        let options = SolveOptions { max_nodes: Some(100_000), ..SolveOptions::default() };
+        b.iter(|| solve_with(unsolvable_deck(), &options).stopped)
+    }
+}
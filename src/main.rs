@@ -1,6 +1,21 @@
 #![feature(impl_trait_in_bindings)]
+#![cfg_attr(test, feature(test))]
+
+#[cfg(test)]
+extern crate test;
+
 pub mod tiles;
+pub mod meld;
+pub mod rules;
 pub mod solve;
+pub mod board;
+pub mod tileset;
+pub mod notation;
+pub mod table;
+pub mod solver;
+pub mod exact_cover;
+#[cfg(feature = "serde")]
+pub mod serialize;
 
 #[cfg(target_arch = "wasm32")]
 mod web;
@@ -14,6 +29,9 @@ fn main() {
 }
 
 #[cfg(not(target_arch = "wasm32"))]
-fn main() -> Result<(), tiles::TilesError> {
-    cli::main()
+fn main() {
+    if let Err(e) = cli::main() {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    }
 }
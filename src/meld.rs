@@ -0,0 +1,647 @@
+use std::fmt::Display;
+use super::tiles::*;
+use super::rules::RuleSet;
+
+/// The shape of a meld, listing every position including those
+/// played by jokers.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum MeldKind {
+    /// Consecutive ranks of a single color, starting at `start`. If
+    /// wrapping around is allowed, a run can carry on from 13 to 1.
+    Run { color: Color, start: u8, len: u8 },
+    /// A single rank in different colors, in color order
+    Group { rank: u8, colors: Vec<Color> },
+}
+
+impl MeldKind {
+    /// The tiles making up this meld, in the order they are laid out.
+    /// A run which doesn't start at 1 to 13 has positions outside the
+    /// standard set of tiles.
+    pub fn positions(&self) -> Vec<Tile> {
+        match self {
+            MeldKind::Run { color, start, len } => {
+                (0..*len)
+                    .map(|i| match *start {
+                        1..=13 => Tile::Number(((u16::from(*start) - 1 + u16::from(i)) % 13 + 1) as u8, *color),
+                        _ => Tile::Number(*start, *color),
+                    })
+                    .collect()
+            }
+            MeldKind::Group { rank, colors } => {
+                colors.iter().map(|color| Tile::Number(*rank, *color)).collect()
+            }
+        }
+    }
+}
+
+/// A meld on the board: its shape, together with which positions are
+/// played by jokers.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Meld {
+    pub kind: MeldKind,
+    /// The tiles which jokers in this meld stand in for
+    pub jokers: Tiles,
+    /// Which of those jokers are special jokers. The rest are plain
+    /// jokers.
+    pub specials: Tiles,
+}
+
+impl Meld {
+    /// The tiles in this meld in order, along with whether each one
+    /// is played by a joker.
+    pub fn layout(&self) -> Vec<(Tile, bool)> {
+        let mut jokers = self.jokers;
+        self.kind
+            .positions()
+            .into_iter()
+            .map(|tile| {
+                let count = jokers.get_count(&tile);
+                if count > 0 {
+                    jokers.set_count(&tile, count - 1);
+                }
+                (tile, count > 0)
+            })
+            .collect()
+    }
+
+    /// The physical jokers in this meld, in the order they are laid
+    /// out: plain jokers first, and then any special jokers.
+    pub(crate) fn joker_tiles(&self) -> Vec<Tile> {
+        let plain = self.jokers.get_total_count() - self.specials.get_total_count();
+        let mut res = vec![Tile::Joker; usize::from(plain)];
+        for joker in Tile::jokers() {
+            for _ in 0..self.specials.get_count(&joker) {
+                res.push(joker);
+            }
+        }
+        res
+    }
+
+    /// The physical tiles making up this meld, with jokers as
+    /// `Tile::Joker` or the special joker played.
+    pub fn tiles(&self) -> Tiles {
+        let mut tiles = Tiles::new();
+        let mut jokers = self.joker_tiles().into_iter();
+        for (tile, is_joker) in self.layout() {
+            let tile = if is_joker { jokers.next().unwrap() } else { tile };
+            tiles.add_tile(&tile).unwrap();
+        }
+        tiles
+    }
+
+    /// An equivalent meld, made of the same physical tiles, with the
+    /// jokers standing in for canonical tiles. Jokers at the ends of
+    /// a run go after the natural tiles where there is room, and
+    /// jokers in a group take the lowest missing colors.
+    pub fn normalized(&self) -> Meld {
+        let naturals: Vec<Tile> = self
+            .layout()
+            .into_iter()
+            .filter(|(_, is_joker)| !is_joker)
+            .map(|(tile, _)| tile)
+            .collect();
+
+        let kind = match &self.kind {
+            MeldKind::Run { color, start, len } => {
+                let layout = self.layout();
+                let first = layout.iter().position(|(_, is_joker)| !is_joker);
+                let last = layout.iter().rposition(|(_, is_joker)| !is_joker);
+                let (first, last) = match (first, last) {
+                    (Some(first), Some(last)) => (first as u8, last as u8),
+                    _ => return self.clone(),
+                };
+                let spare = *len - (last - first + 1);
+                let first = (*start - 1 + first) % 13 + 1;
+                let last = (*start - 1 + last) % 13 + 1;
+                // A run which already wraps around can have all of
+                // its jokers at the end
+                let below = if *start + *len - 1 > 13 {
+                    0
+                } else {
+                    spare.saturating_sub(13 - last)
+                };
+                MeldKind::Run { color: *color, start: first - below, len: *len }
+            }
+            MeldKind::Group { rank, colors } => {
+                let mut natural_colors: Vec<Color> = naturals
+                    .iter()
+                    .filter_map(|tile| match tile {
+                        Tile::Number(_, color) => Some(*color),
+                        Tile::Joker | Tile::Special(_) => None,
+                    })
+                    .collect();
+                // Groups with more than one tile of a color can run
+                // out of missing colors, in which case the jokers
+                // keep their colors.
+                let joker_colors: Vec<Color> = colors
+                    .iter()
+                    .filter(|color| self.jokers.get_count(&Tile::Number(*rank, **color)) > 0)
+                    .cloned()
+                    .collect();
+                let missing: Vec<Color> = Color::all()
+                    .filter(|color| !natural_colors.contains(color))
+                    .chain(joker_colors)
+                    .take(colors.len() - natural_colors.len())
+                    .collect();
+                natural_colors.extend(missing);
+                natural_colors.sort();
+                MeldKind::Group { rank: *rank, colors: natural_colors }
+            }
+        };
+
+        let mut natural_tiles = Tiles::new();
+        for tile in &naturals {
+            natural_tiles.add_tile(tile).unwrap();
+        }
+        let mut jokers = Tiles::new();
+        for tile in kind.positions() {
+            let count = natural_tiles.get_count(&tile);
+            if count > 0 {
+                natural_tiles.set_count(&tile, count - 1);
+            } else {
+                jokers.add_tile(&tile).unwrap();
+            }
+        }
+        Meld {
+            kind,
+            jokers,
+            specials: self.specials,
+        }
+    }
+
+    /// The total face value of the meld, with each joker valued as
+    /// the tile it stands in for.
+    pub fn value(&self) -> u64 {
+        self.kind
+            .positions()
+            .iter()
+            .map(|tile| u64::from(tile.penalty()))
+            .sum()
+    }
+
+    /// Check that this meld follows the given rules
+    pub fn check(&self, rules: &RuleSet) -> Result<(), MeldError> {
+        match self.kind {
+            MeldKind::Run { start: 1..=13, .. } | MeldKind::Group { .. } => (),
+            MeldKind::Run { start, .. } => return Err(MeldError::InvalidStart(start)),
+        }
+        let positions = self.kind.positions();
+        let len = positions.len();
+        if let Some(tile) = positions.iter().find(|tile| !tile.is_standard()) {
+            return Err(MeldError::NotInTileSet(*tile));
+        }
+        if len < usize::from(rules.min_meld_size) || len == 0 {
+            return Err(MeldError::TooShort);
+        }
+
+        let is_run = match &self.kind {
+            MeldKind::Run { start, len, .. } => {
+                if *len > rules.max_run_length.min(13) {
+                    return Err(MeldError::TooLong);
+                }
+                if u16::from(*start) + u16::from(*len) - 1 > 13 && !rules.wrap_around {
+                    return Err(MeldError::WrapsAround);
+                }
+                true
+            }
+            MeldKind::Group { colors, .. } => {
+                if len > usize::from(rules.max_group_size) {
+                    return Err(MeldError::TooLong);
+                }
+                let most = if rules.duplicate_group_colors { 2 } else { 1 };
+                if let Some(color) = Color::all().find(|color| colors.iter().filter(|c| *c == color).count() > most) {
+                    return Err(MeldError::DuplicateColor(color));
+                }
+                false
+            }
+        };
+
+        let position_tiles: Tiles = positions.iter().cloned().collect();
+        let joker_count = self.jokers.get_total_count();
+        if !self.jokers.is_subset(&position_tiles)
+            || self.specials.get_total_count() > joker_count
+            || self.specials.iter().any(|(tile, _)| !tile.is_joker())
+        {
+            return Err(MeldError::InvalidJokers);
+        }
+        if len - usize::from(joker_count) < usize::from(rules.min_naturals) {
+            return Err(MeldError::TooFewNaturals);
+        }
+        let too_many_jokers = match rules.max_jokers {
+            None => false,
+            Some(max) => joker_count > max,
+        };
+        if too_many_jokers {
+            return Err(MeldError::TooManyJokers);
+        }
+        if is_run && !rules.adjacent_jokers && self.layout().windows(2).any(|pair| pair[0].1 && pair[1].1) {
+            return Err(MeldError::AdjacentJokers);
+        }
+        for (joker, _) in self.specials.iter() {
+            let joker_rules = rules.joker_rules(&joker).unwrap();
+            if !(if is_run { joker_rules.in_runs } else { joker_rules.in_groups }) {
+                return Err(MeldError::JokerNotAllowed(joker));
+            }
+        }
+        Ok(())
+    }
+
+    /// Every way the given tiles make a valid meld under the standard
+    /// rules, see `classify_with`.
+    pub fn classify(tiles: &Tiles) -> Vec<Meld> {
+        Meld::classify_with(tiles, &RuleSet::standard())
+    }
+
+    /// Every way the given tiles make a valid meld under `rules`. The
+    /// same tiles can often be read in more than one way: `5R 6R J`
+    /// is either `J=4R 5R 6R` or `5R 6R J=7R`, and `5B 5U J` could
+    /// be a group with the joker as either of the other colors.
+    pub fn classify_with(tiles: &Tiles, rules: &RuleSet) -> Vec<Meld> {
+        let mut naturals = Tiles::new();
+        let mut specials = Tiles::new();
+        let mut joker_count = 0;
+        for (tile, count) in tiles.iter() {
+            match tile {
+                Tile::Number(..) => naturals.set_count(&tile, count),
+                Tile::Joker => joker_count += count,
+                Tile::Special(_) => {
+                    joker_count += count;
+                    specials.set_count(&tile, count);
+                }
+            }
+        }
+        let (first_rank, first_color) = match naturals.iter().next() {
+            Some((Tile::Number(rank, color), _)) => (rank, color),
+            _ => return vec![],
+        };
+        let len = tiles.get_total_count();
+
+        let mut kinds = vec![];
+        let same_color = naturals.iter().all(|(tile, _)| match tile {
+            Tile::Number(_, color) => color == first_color,
+            Tile::Joker | Tile::Special(_) => false,
+        });
+        let same_rank = naturals.iter().all(|(tile, _)| match tile {
+            Tile::Number(rank, _) => rank == first_rank,
+            Tile::Joker | Tile::Special(_) => false,
+        });
+        if same_color && len <= 13 {
+            for start in 1..=13 {
+                kinds.push(MeldKind::Run { color: first_color, start, len });
+            }
+        }
+        if same_rank {
+            // Every choice of colors for the jokers, in color order
+            let mut choices: Vec<Vec<Color>> = vec![vec![]];
+            for _ in 0..joker_count {
+                choices = choices
+                    .into_iter()
+                    .flat_map(|choice| {
+                        Color::all()
+                            .filter(|color| match choice.last() {
+                                None => true,
+                                Some(last) => color >= last,
+                            })
+                            .map(|color| {
+                                let mut choice = choice.clone();
+                                choice.push(color);
+                                choice
+                            })
+                            .collect::<Vec<_>>()
+                    })
+                    .collect();
+            }
+            for mut colors in choices {
+                for (tile, count) in naturals.iter() {
+                    if let Tile::Number(_, color) = tile {
+                        for _ in 0..count {
+                            colors.push(color);
+                        }
+                    }
+                }
+                colors.sort();
+                kinds.push(MeldKind::Group { rank: first_rank, colors });
+            }
+        }
+
+        kinds
+            .into_iter()
+            .filter_map(|kind| {
+                // The naturals have to fill some of the positions, and
+                // jokers the rest
+                let mut left = naturals;
+                let mut jokers = Tiles::new();
+                for tile in kind.positions() {
+                    let count = left.get_count(&tile);
+                    if count > 0 {
+                        left.set_count(&tile, count - 1);
+                    } else if jokers.add_tile(&tile).is_err() {
+                        return None;
+                    }
+                }
+                let meld = Meld { kind, jokers, specials };
+                if left.is_empty() && meld.check(rules).is_ok() {
+                    Some(meld)
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }
+
+    /// Every way the given tiles, in the order they are laid out,
+    /// make a valid meld under `rules`. Jokers in a run stand in for
+    /// the tile at their place, so `5R J 7R` can only be `5R J=6R 7R`.
+    /// Groups can be laid out in any order.
+    pub fn classify_layout(layout: &[Tile], rules: &RuleSet) -> Vec<Meld> {
+        let mut tiles = Tiles::new();
+        for tile in layout {
+            if tiles.add_tile(tile).is_err() {
+                return vec![];
+            }
+        }
+        Meld::classify_with(&tiles, rules)
+            .into_iter()
+            .filter(|meld| match meld.kind {
+                MeldKind::Group { .. } => true,
+                MeldKind::Run { .. } => meld.layout().iter().zip(layout).all(|((tile, is_joker), laid)| {
+                    if laid.is_joker() { *is_joker } else { !is_joker && tile == laid }
+                }),
+            })
+            .collect()
+    }
+}
+
+/// Why a meld doesn't follow the rules, see `Meld::check`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MeldError {
+    /// A run which doesn't start at a rank from 1 to 13
+    InvalidStart(u8),
+    /// A position which isn't in the standard set of tiles
+    NotInTileSet(Tile),
+    /// Fewer tiles than `RuleSet::min_meld_size`
+    TooShort,
+    /// A run or group longer than the rules allow
+    TooLong,
+    /// A run which carries on past 13 without the wrap around rule
+    WrapsAround,
+    /// A group with more of a color than the rules allow
+    DuplicateColor(Color),
+    /// The jokers don't match the positions of the meld
+    InvalidJokers,
+    /// Fewer natural tiles than `RuleSet::min_naturals`
+    TooFewNaturals,
+    /// More jokers than `RuleSet::max_jokers`
+    TooManyJokers,
+    /// Two jokers next to each other in a run, when that isn't
+    /// allowed
+    AdjacentJokers,
+    /// A special joker which can't go in this kind of meld
+    JokerNotAllowed(Tile),
+}
+
+impl Display for MeldError {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            MeldError::InvalidStart(start) => write!(fmt, "Runs cannot start at {}", start),
+            MeldError::NotInTileSet(tile) => write!(fmt, "{} is not in the tile set", tile),
+            MeldError::TooShort => write!(fmt, "Too few tiles"),
+            MeldError::TooLong => write!(fmt, "Too many tiles"),
+            MeldError::WrapsAround => write!(fmt, "Runs cannot carry on past 13"),
+            MeldError::DuplicateColor(color) => write!(fmt, "Too many tiles of color {}", color),
+            MeldError::InvalidJokers => write!(fmt, "The jokers do not fit the meld"),
+            MeldError::TooFewNaturals => write!(fmt, "Too few tiles which aren't jokers"),
+            MeldError::TooManyJokers => write!(fmt, "Too many jokers"),
+            MeldError::AdjacentJokers => write!(fmt, "Jokers cannot be next to each other"),
+            MeldError::JokerNotAllowed(joker) => write!(fmt, "{} cannot go in this kind of meld", joker),
+        }
+    }
+}
+
+impl std::error::Error for MeldError {}
+
+impl Display for Meld {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let mut jokers = self.joker_tiles().into_iter();
+        for (i, (tile, is_joker)) in self.layout().into_iter().enumerate() {
+            if i > 0 {
+                write!(fmt, " ")?;
+            }
+            if is_joker {
+                write!(fmt, "{}={}", jokers.next().unwrap(), tile)?;
+            } else {
+                write!(fmt, "{}", tile)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_run_layout() {
+        let meld = Meld {
+            kind: MeldKind::Run { color: Color::Red, start: 1, len: 3 },
+            jokers: "2R".parse().unwrap(),
+            specials: Tiles::new(),
+        };
+        assert_eq!(meld.to_string(), "1R J=2R 3R");
+        assert_eq!(meld.tiles(), "1R 3R J".parse().unwrap());
+        assert_eq!(meld.value(), 6);
+    }
+
+    #[test]
+    fn test_group_layout() {
+        let meld = Meld {
+            kind: MeldKind::Group {
+                rank: 7,
+                colors: vec![Color::Black, Color::Orange, Color::Red],
+            },
+            jokers: Tiles::new(),
+            specials: Tiles::new(),
+        };
+        assert_eq!(meld.to_string(), "7B 7O 7R");
+        assert_eq!(meld.layout().len(), 3);
+        assert_eq!(meld.value(), 21);
+    }
+
+    #[test]
+    fn test_normalize_run() {
+        let meld = Meld {
+            kind: MeldKind::Run { color: Color::Red, start: 4, len: 4 },
+            jokers: "4R 6R".parse().unwrap(),
+            specials: Tiles::new(),
+        };
+        let normalized = meld.normalized();
+        assert_eq!(normalized.to_string(), "5R J=6R 7R J=8R");
+        assert_eq!(normalized.tiles(), meld.tiles());
+        assert_eq!(normalized.normalized(), normalized);
+    }
+
+    #[test]
+    fn test_normalize_run_at_top() {
+        let meld = Meld {
+            kind: MeldKind::Run { color: Color::Blue, start: 10, len: 4 },
+            jokers: "10U 11U".parse().unwrap(),
+            specials: Tiles::new(),
+        };
+        assert_eq!(meld.normalized(), meld);
+    }
+
+    #[test]
+    fn test_wrapped_run() {
+        let meld = Meld {
+            kind: MeldKind::Run { color: Color::Orange, start: 12, len: 4 },
+            jokers: "1O".parse().unwrap(),
+            specials: Tiles::new(),
+        };
+        assert_eq!(meld.to_string(), "12O 13O J=1O 2O");
+        assert_eq!(meld.value(), 12 + 13 + 1 + 2);
+        assert_eq!(meld.normalized(), meld);
+
+        let meld = Meld {
+            kind: MeldKind::Run { color: Color::Orange, start: 11, len: 4 },
+            jokers: "11O".parse().unwrap(),
+            specials: Tiles::new(),
+        };
+        assert_eq!(meld.normalized().to_string(), "12O 13O 1O J=2O");
+    }
+
+    #[test]
+    fn test_special_jokers() {
+        let meld = Meld {
+            kind: MeldKind::Run { color: Color::Red, start: 1, len: 4 },
+            jokers: "2R 4R".parse().unwrap(),
+            specials: "J2".parse().unwrap(),
+        };
+        assert_eq!(meld.to_string(), "1R J=2R 3R J2=4R");
+        assert_eq!(meld.tiles(), "1R 3R J J2".parse().unwrap());
+        assert_eq!(meld.normalized().tiles(), meld.tiles());
+    }
+
+    #[test]
+    fn test_normalize_group() {
+        let meld = Meld {
+            kind: MeldKind::Group {
+                rank: 3,
+                colors: vec![Color::Blue, Color::Orange, Color::Red],
+            },
+            jokers: "3U".parse().unwrap(),
+            specials: Tiles::new(),
+        };
+        assert_eq!(meld.normalized().to_string(), "J=3B 3O 3R");
+    }
+
+    fn classify_helper(tiles: &str, rules: &RuleSet) -> Vec<String> {
+        let melds = Meld::classify_with(&tiles.parse().unwrap(), rules);
+        melds.iter().map(|meld| meld.to_string()).collect()
+    }
+
+    fn layout_helper(layout: &str, rules: &RuleSet) -> Vec<String> {
+        let layout: Vec<Tile> = layout.split(' ').map(|tile| tile.parse().unwrap()).collect();
+        let melds = Meld::classify_layout(&layout, rules);
+        melds.iter().map(|meld| meld.to_string()).collect()
+    }
+
+    #[test]
+    fn test_classify() {
+        let rules = RuleSet::standard();
+        assert_eq!(classify_helper("5R 6R J", &rules), vec!["J=4R 5R 6R", "5R 6R J=7R"]);
+        assert_eq!(classify_helper("5R 7R J", &rules), vec!["5R J=6R 7R"]);
+        assert_eq!(classify_helper("1R 2R 3R", &rules), vec!["1R 2R 3R"]);
+        assert_eq!(classify_helper("12R 13R J", &rules), vec!["J=11R 12R 13R"]);
+        assert_eq!(classify_helper("5B 5U J", &rules), vec!["5B 5U J=5O", "5B 5U J=5R"]);
+        assert_eq!(classify_helper("5B 5U J J", &rules), vec!["5B 5U J=5O J=5R"]);
+        assert_eq!(classify_helper("5B 5U 5O 5R", &rules), vec!["5B 5U 5O 5R"]);
+        assert_eq!(Meld::classify(&"1R 1R 2R".parse().unwrap()), vec![]);
+        assert_eq!(Meld::classify(&"1R 2B 3R".parse().unwrap()), vec![]);
+        assert_eq!(Meld::classify(&"1R 2R".parse().unwrap()), vec![]);
+        assert_eq!(Meld::classify(&"J J J1".parse().unwrap()), vec![]);
+        assert_eq!(Meld::classify(&"5R 5R 5B".parse().unwrap()), vec![]);
+        // A single natural and two jokers could be a run or a group
+        let rules = RuleSet { min_naturals: 1, ..RuleSet::standard() };
+        assert_eq!(classify_helper("13B J J", &rules).len(), 1 + 3);
+
+        for tiles in &["5R 6R J", "5B 5U J J1", "1R 2R 3R 4R J 6R", "9O 9U 9B"] {
+            for meld in Meld::classify(&tiles.parse().unwrap()) {
+                assert_eq!(meld.tiles(), tiles.parse().unwrap());
+                assert_eq!(meld.check(&RuleSet::standard()), Ok(()));
+            }
+        }
+    }
+
+    #[test]
+    fn test_classify_rules() {
+        let mut rules = RuleSet::standard();
+        rules.set("wrap-around=true").unwrap();
+        assert_eq!(classify_helper("13R 1R J", &rules), vec!["J=12R 13R 1R", "13R 1R J=2R"]);
+        assert_eq!(classify_helper("13R 1R J", &RuleSet::standard()), Vec::<String>::new());
+
+        let mut rules = RuleSet::standard();
+        rules.set("duplicate-group-colors=true").unwrap();
+        assert_eq!(classify_helper("5R 5R 5B", &rules), vec!["5B 5R 5R"]);
+
+        let mut rules = RuleSet::standard();
+        rules.set("joker1=groups").unwrap();
+        assert_eq!(classify_helper("5R 6R J1", &rules), Vec::<String>::new());
+        assert_eq!(classify_helper("5R 5B J1", &rules), vec!["5B J1=5U 5R", "5B J1=5O 5R"]);
+    }
+
+    #[test]
+    fn test_classify_layout() {
+        let rules = RuleSet::standard();
+        assert_eq!(layout_helper("5R J 7R", &rules), vec!["5R J=6R 7R"]);
+        assert_eq!(layout_helper("J 5R 6R", &rules), vec!["J=4R 5R 6R"]);
+        assert_eq!(layout_helper("5R 6R J", &rules), vec!["5R 6R J=7R"]);
+        assert_eq!(layout_helper("6R 5R J", &rules), Vec::<String>::new());
+        assert_eq!(layout_helper("5O J 5B", &rules), vec!["5B J=5U 5O", "5B 5O J=5R"]);
+        assert_eq!(layout_helper("J J J", &rules), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_check() {
+        let run = |start, len, jokers: &str| Meld {
+            kind: MeldKind::Run { color: Color::Red, start, len },
+            jokers: jokers.parse().unwrap(),
+            specials: Tiles::new(),
+        };
+        let rules = RuleSet::standard();
+        assert_eq!(run(1, 3, "").check(&rules), Ok(()));
+        assert_eq!(run(1, 2, "").check(&rules), Err(MeldError::TooShort));
+        assert_eq!(run(12, 3, "").check(&rules), Err(MeldError::WrapsAround));
+        assert_eq!(run(1, 3, "2R 3R").check(&rules), Err(MeldError::TooFewNaturals));
+        assert_eq!(run(1, 3, "5R").check(&rules), Err(MeldError::InvalidJokers));
+        assert_eq!(run(1, 4, "2R 3R").check(&rules), Ok(()));
+        let strict = RuleSet { adjacent_jokers: false, ..RuleSet::standard() };
+        assert_eq!(run(1, 4, "2R 3R").check(&strict), Err(MeldError::AdjacentJokers));
+        assert_eq!(run(1, 4, "2R 4R").check(&strict), Ok(()));
+        let strict = RuleSet { max_jokers: Some(1), max_run_length: 5, ..RuleSet::standard() };
+        assert_eq!(run(1, 4, "2R 4R").check(&strict), Err(MeldError::TooManyJokers));
+        assert_eq!(run(1, 6, "").check(&strict), Err(MeldError::TooLong));
+        assert_eq!(run(0, 3, "").check(&rules), Err(MeldError::InvalidStart(0)));
+        assert_eq!(run(14, 3, "").check(&rules), Err(MeldError::InvalidStart(14)));
+        assert_eq!(run(255, 255, "").check(&rules), Err(MeldError::InvalidStart(255)));
+        assert_eq!(run(1, 0, "").check(&rules), Err(MeldError::TooShort));
+        assert_eq!(run(13, 255, "").check(&rules), Err(MeldError::TooLong));
+        assert_eq!(run(0, 0, "").to_string(), "");
+
+        let group = Meld {
+            kind: MeldKind::Group { rank: 5, colors: vec![Color::Black, Color::Red, Color::Red] },
+            jokers: Tiles::new(),
+            specials: Tiles::new(),
+        };
+        assert_eq!(group.check(&rules), Err(MeldError::DuplicateColor(Color::Red)));
+        let group = Meld {
+            kind: MeldKind::Group { rank: 5, colors: vec![Color::Black, Color::Red, Color::Green] },
+            ..group
+        };
+        assert_eq!(group.check(&rules), Err(MeldError::NotInTileSet(Tile::Number(5, Color::Green))));
+        assert_eq!(
+            MeldError::JokerNotAllowed(Tile::Special(1)).to_string(),
+            "J1 cannot go in this kind of meld"
+        );
+    }
+}
@@ -0,0 +1,435 @@
+use super::tiles::*;
+use super::meld::Meld;
+use super::solve::{RackSolution, Solution};
+use super::board::Board;
+
+/// A way of writing tiles down. `Tile`'s own `FromStr` and `Display`
+/// use the standard notation, `7R`, and other notations can be used
+/// for reading and writing tiles through this trait instead.
+pub trait Notation {
+    /// Parse a single tile, or `None` if it isn't one
+    fn parse_tile(&self, s: &str) -> Option<Tile>;
+
+    fn format_tile(&self, tile: &Tile) -> String;
+
+    /// What separates the tiles in a list. Notations which have
+    /// spaces inside tiles separate them with commas.
+    fn separator(&self) -> char {
+        ' '
+    }
+
+    /// Parse a list of tiles. Errors give the position of the tile
+    /// like parsing `Tiles` does.
+    fn parse_tiles(&self, s: &str) -> Result<Tiles, TilesError> {
+        Ok(self.parse_layout(s)?.into_iter().collect())
+    }
+
+    /// Parse a list of tiles like `parse_tiles`, keeping them in the
+    /// order they are written, for example to see where the jokers
+    /// in a run are.
+    fn parse_layout(&self, s: &str) -> Result<Vec<Tile>, TilesError> {
+        let mut tiles = Tiles::new();
+        let mut layout = vec![];
+        let separator = self.separator();
+        // Whitespace other than a space always separates tiles, even
+        // in notations which have spaces inside tiles
+        let is_separator = |c: char| c == separator || (c.is_whitespace() && c != ' ');
+        for (index, (offset, token)) in tokens(s, is_separator).into_iter().enumerate() {
+            let at = |error| TilesError::At { offset, index, error: Box::new(error) };
+            let tile = self
+                .parse_tile(token)
+                .ok_or_else(|| at(TilesError::InvalidTileString(String::from(token))))?;
+            tiles.add_tile(&tile).map_err(at)?;
+            layout.push(tile);
+        }
+        Ok(layout)
+    }
+
+    fn format_tiles(&self, tiles: &Tiles) -> String {
+        let mut names = vec![];
+        for tile in Tile::all() {
+            for _ in 0..tiles.get_count(&tile) {
+                names.push(self.format_tile(&tile));
+            }
+        }
+        names.join(&joiner(self.separator()))
+    }
+
+    /// Write a meld like its `Display`, with jokers as `J=7R`
+    fn format_meld(&self, meld: &Meld) -> String {
+        let mut jokers = meld.joker_tiles().into_iter();
+        let tiles: Vec<String> = meld
+            .layout()
+            .into_iter()
+            .map(|(tile, is_joker)| {
+                if is_joker {
+                    let joker = jokers.next().unwrap();
+                    format!("{}={}", self.format_tile(&joker), self.format_tile(&tile))
+                } else {
+                    self.format_tile(&tile)
+                }
+            })
+            .collect();
+        tiles.join(&joiner(self.separator()))
+    }
+
+    /// Write a board like its `Display`, with melds separated by `|`
+    fn format_board(&self, board: &Board) -> String {
+        let melds: Vec<String> = board.melds.iter().map(|meld| self.format_meld(meld)).collect();
+        melds.join(" | ")
+    }
+
+    /// Write a solution like its `Display`
+    fn format_solution(&self, solution: &Solution) -> String {
+        let mut res = String::new();
+        for combo in &solution.combos {
+            res.push_str(&format!("({}), ", self.format_meld(combo)));
+        }
+        res.push_str(&format!("leftover jokers: {}", solution.leftover_jokers));
+        res
+    }
+
+    /// Write a solution from a rack like its `Display`
+    fn format_rack_solution(&self, solution: &RackSolution) -> String {
+        format!(
+            "{}; played: ({}), remaining: ({}), score: {}",
+            self.format_solution(&solution.solution),
+            self.format_tiles(&solution.played),
+            self.format_tiles(&solution.remaining),
+            solution.score
+        )
+    }
+}
+
+/// Find a notation by name, as given on the command line: `standard`
+/// for `7R`, `color-first` for `R7`, `german` for `7S` with the
+/// German color letters, `yellow` for `7Y` in editions with yellow
+/// tiles instead of orange, `long` for `red-7`, `long-rank-first` for
+/// `7 red`, or `emoji` for `🔴7`.
+pub fn notation(name: &str) -> Option<Box<dyn Notation>> {
+    Some(match name {
+        "standard" => Box::new(Letters::standard()),
+        "color-first" => Box::new(Letters::standard().color_first()),
+        "german" => Box::new(Letters::german()),
+        "yellow" => Box::new(Letters::yellow()),
+        "long" => Box::new(Names { color_first: true }),
+        "long-rank-first" => Box::new(Names { color_first: false }),
+        "emoji" => Box::new(Emoji),
+        _ => return None,
+    })
+}
+
+/// A letter for each color, written after the rank like `7R` or
+/// before it like `R7`. Either order is accepted when parsing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Letters {
+    /// The letters for black, blue, orange and red
+    pub colors: [char; 4],
+    pub joker: char,
+    pub color_first: bool,
+}
+
+impl Letters {
+    /// The notation used by `Tile`'s `FromStr` and `Display`
+    pub fn standard() -> Letters {
+        Letters {
+            colors: ['B', 'U', 'O', 'R'],
+            joker: 'J',
+            color_first: false,
+        }
+    }
+
+    /// Schwarz, blau, gelb and rot, for German editions where the
+    /// orange tiles are yellow
+    pub fn german() -> Letters {
+        Letters {
+            colors: ['S', 'B', 'G', 'R'],
+            ..Letters::standard()
+        }
+    }
+
+    /// For editions with yellow tiles instead of orange
+    pub fn yellow() -> Letters {
+        Letters {
+            colors: ['B', 'U', 'Y', 'R'],
+            ..Letters::standard()
+        }
+    }
+
+    /// The same letters, with the color written before the rank
+    pub fn color_first(self) -> Letters {
+        Letters {
+            color_first: true,
+            ..self
+        }
+    }
+
+    fn color(&self, c: char) -> Option<Color> {
+        let c = c.to_ascii_uppercase();
+        self.colors.iter().zip(Color::all()).find(|(letter, _)| **letter == c).map(|(_, color)| color)
+    }
+
+    /// The letter for the color. Colors beyond the standard four keep
+    /// their usual letter.
+    fn letter(&self, color: Color) -> String {
+        match Color::all().position(|c| c == color) {
+            Some(i) => self.colors[i].to_string(),
+            None => color.to_string(),
+        }
+    }
+}
+
+impl Notation for Letters {
+    fn parse_tile(&self, s: &str) -> Option<Tile> {
+        let mut chars = s.chars();
+        let first = chars.next()?;
+        if first.to_ascii_uppercase() == self.joker {
+            return parse_joker(chars.as_str());
+        }
+        let last = s.chars().last()?;
+        let (color, rank) = match (self.color(first), self.color(last)) {
+            (Some(color), _) => (color, &s[first.len_utf8()..]),
+            (None, Some(color)) => (color, &s[..s.len() - last.len_utf8()]),
+            (None, None) => return None,
+        };
+        Some(Tile::Number(parse_rank(rank)?, color))
+    }
+
+    fn format_tile(&self, tile: &Tile) -> String {
+        match tile {
+            Tile::Joker => self.joker.to_string(),
+            Tile::Special(n) => format!("{}{}", self.joker, n),
+            Tile::Number(rank, color) if self.color_first => format!("{}{}", self.letter(*color), rank),
+            Tile::Number(rank, color) => format!("{}{}", rank, self.letter(*color)),
+        }
+    }
+
+    /// Parse a list of tiles with the same shorthand as `Tiles`, such
+    /// as `3-7R` for a run
+    fn parse_layout(&self, s: &str) -> Result<Vec<Tile>, TilesError> {
+        parse_layout(s, &|token| self.parse_tile(token))
+    }
+}
+
+/// Colors spelled out, like `red-7` or `7 red`. Yellow is accepted
+/// for orange when parsing. As `7 red` has a space in it, lists of
+/// tiles written rank first are separated by commas.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Names {
+    pub color_first: bool,
+}
+
+impl Notation for Names {
+    fn parse_tile(&self, s: &str) -> Option<Tile> {
+        let s = s.to_lowercase();
+        let mut words = s.split(&['-', ' '][..]).filter(|word| !word.is_empty());
+        let first = words.next()?;
+        let second = words.next();
+        if words.next().is_some() {
+            return None;
+        }
+        if first == "joker" {
+            return parse_joker(second.unwrap_or(""));
+        }
+        let (name, rank) = match first.parse::<u8>() {
+            Ok(_) => (second?, first),
+            Err(_) => (first, second?),
+        };
+        let color = match name {
+            "black" => Color::Black,
+            "blue" => Color::Blue,
+            "orange" | "yellow" => Color::Orange,
+            "red" => Color::Red,
+            _ => return None,
+        };
+        Some(Tile::Number(parse_rank(rank)?, color))
+    }
+
+    fn format_tile(&self, tile: &Tile) -> String {
+        let separator = if self.color_first { '-' } else { ' ' };
+        match tile {
+            Tile::Joker => String::from("joker"),
+            Tile::Special(n) => format!("joker{}{}", separator, n),
+            Tile::Number(rank, color) => {
+                let name = match color {
+                    Color::Black => "black",
+                    Color::Blue => "blue",
+                    Color::Orange => "orange",
+                    Color::Red => "red",
+                    Color::Green => "green",
+                    Color::Purple => "purple",
+                };
+                if self.color_first {
+                    format!("{}-{}", name, rank)
+                } else {
+                    format!("{} {}", rank, name)
+                }
+            }
+        }
+    }
+
+    fn separator(&self) -> char {
+        if self.color_first { ' ' } else { ',' }
+    }
+}
+
+/// A colored circle followed by the rank, like `🔴7`, and `🃏` for
+/// jokers. The circle can also go after the rank when parsing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Emoji;
+
+const EMOJI: [(Color, &str); 6] = [
+    (Color::Black, "⚫"),
+    (Color::Blue, "🔵"),
+    (Color::Orange, "🟠"),
+    (Color::Red, "🔴"),
+    (Color::Green, "🟢"),
+    (Color::Purple, "🟣"),
+];
+
+const JOKER_EMOJI: &str = "🃏";
+
+impl Notation for Emoji {
+    fn parse_tile(&self, s: &str) -> Option<Tile> {
+        if let Some(rest) = without(s, JOKER_EMOJI) {
+            return parse_joker(rest);
+        }
+        for (color, emoji) in EMOJI.iter().take(4) {
+            if let Some(rank) = without(s, emoji) {
+                return Some(Tile::Number(parse_rank(rank)?, *color));
+            }
+        }
+        None
+    }
+
+    fn format_tile(&self, tile: &Tile) -> String {
+        match tile {
+            Tile::Joker => String::from(JOKER_EMOJI),
+            Tile::Special(n) => format!("{}{}", JOKER_EMOJI, n),
+            Tile::Number(rank, color) => {
+                let emoji = EMOJI.iter().find(|(c, _)| c == color).unwrap().1;
+                format!("{}{}", emoji, rank)
+            }
+        }
+    }
+}
+
+/// What is left of `s` after taking `affix` off either end of it
+fn without<'a>(s: &'a str, affix: &str) -> Option<&'a str> {
+    match s.find(affix) {
+        Some(0) => Some(&s[affix.len()..]),
+        Some(i) if i + affix.len() == s.len() => Some(&s[..i]),
+        _ => None,
+    }
+}
+
+/// How tiles are joined when writing a list of them
+fn joiner(separator: char) -> String {
+    match separator {
+        ' ' => String::from(" "),
+        c => format!("{} ", c),
+    }
+}
+
+/// A rank from 1 to 13
+fn parse_rank(s: &str) -> Option<u8> {
+    match s.trim().parse() {
+        Ok(rank @ 1..=13) => Some(rank),
+        _ => None,
+    }
+}
+
+/// What follows the joker letter or word: nothing for the plain
+/// joker, or the number of a special joker
+fn parse_joker(s: &str) -> Option<Tile> {
+    if s.is_empty() {
+        return Some(Tile::Joker);
+    }
+    match s.parse() {
+        Ok(n @ 1..=SPECIAL_JOKERS) => Some(Tile::Special(n)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use super::super::solve::solve;
+
+    const NAMES: [&str; 7] = ["standard", "color-first", "german", "yellow", "long", "long-rank-first", "emoji"];
+
+    #[test]
+    fn test_standard_matches_display() {
+        let standard = notation("standard").unwrap();
+        for tile in Tile::all() {
+            assert_eq!(standard.format_tile(&tile), tile.to_string());
+            assert_eq!(standard.parse_tile(&tile.to_string()), Some(tile));
+        }
+        let tiles: Tiles = "1R 2R 3R J 5B 5U J1".parse().unwrap();
+        assert_eq!(standard.format_tiles(&tiles), tiles.to_string());
+        for solution in solve(tiles) {
+            assert_eq!(standard.format_solution(&solution), solution.to_string());
+        }
+    }
+
+    #[test]
+    fn test_examples() {
+        let tile = Tile::Number(7, Color::Red);
+        let examples = [
+            ("color-first", "R7"),
+            ("german", "7R"),
+            ("long", "red-7"),
+            ("long-rank-first", "7 red"),
+            ("emoji", "🔴7"),
+        ];
+        for (name, s) in examples.iter() {
+            let notation = notation(name).unwrap();
+            assert_eq!(notation.format_tile(&tile), *s);
+            assert_eq!(notation.parse_tile(s), Some(tile));
+        }
+
+        let german = Letters::german();
+        assert_eq!(german.parse_tiles("3S 3b 3G"), "3B 3U 3O".parse());
+        assert_eq!(german.parse_tiles("3SBG, 2x1-3R"), "3B 3U 3O 1R 1R 2R 2R 3R 3R".parse());
+        assert_eq!(Letters::yellow().parse_tile("y12"), Some(Tile::Number(12, Color::Orange)));
+        let long = Names { color_first: false };
+        assert_eq!(long.parse_tiles("7 red, 8 yellow,joker"), "7R 8O J".parse());
+        assert_eq!(long.parse_tiles("7 red\t8 yellow\njoker"), "7R 8O J".parse());
+        assert_eq!(Emoji.parse_tiles("🔴7\t🟠8"), "7R 8O".parse());
+        assert_eq!(long.format_tiles(&"7R 8O J".parse().unwrap()), "7 red, 8 orange, joker");
+        assert_eq!(Emoji.parse_tiles("5⚫ 🔵5 🃏2"), "5B 5U J2".parse());
+        assert!(notation("klingon").is_none());
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(Letters::german().parse_tile("7U"), None);
+        assert_eq!(Letters::standard().parse_tile("14R"), None);
+        assert_eq!(Names { color_first: true }.parse_tile("red-7-8"), None);
+        assert_eq!(Emoji.parse_tile("🟢7"), None);
+        let e = Names { color_first: false }.parse_tiles("7 red, 7 pink").unwrap_err();
+        assert_eq!(e.position(), Some((7, 1)));
+        assert_eq!(e.inner(), &TilesError::InvalidTileString(String::from("7 pink")));
+    }
+
+    #[test]
+    fn test_parse_display() {
+        for name in NAMES.iter() {
+            let notation = notation(name).unwrap();
+            let helper = |tiles: &Tiles| {
+                let s = notation.format_tiles(tiles);
+                assert_eq!(&notation.parse_tiles(&s).unwrap(), tiles, "{}: {}", name, s);
+            };
+
+            let mut tiles = Tiles::new();
+            helper(&tiles);
+            for tile in Tile::all() {
+                assert_eq!(notation.parse_tile(&notation.format_tile(&tile)), Some(tile));
+                tiles.add_tile(&tile).unwrap();
+                helper(&tiles);
+                tiles.add_tile(&tile).unwrap();
+                helper(&tiles);
+            }
+        }
+    }
+}
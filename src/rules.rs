@@ -0,0 +1,265 @@
+use super::tiles::{Tile, SPECIAL_JOKERS};
+
+/// The rules which runs and groups have to follow. The default is
+/// the standard Rummikub rules, and each field can be changed to
+/// play with common house rules.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RuleSet {
+    /// The fewest tiles in a run or group
+    pub min_meld_size: u8,
+    /// The most tiles in a group
+    pub max_group_size: u8,
+    /// The most tiles in a run. Runs can never be longer than 13.
+    pub max_run_length: u8,
+    /// The fewest natural tiles, that is not jokers, in each meld
+    pub min_naturals: u8,
+    /// The most jokers in each meld, if there is a limit
+    pub max_jokers: Option<u8>,
+    /// Whether a run can carry on from 13 back to 1, like `12R 13R 1R`
+    pub wrap_around: bool,
+    /// Whether two jokers can be next to each other in a run
+    pub adjacent_jokers: bool,
+    /// Whether a group can have more than one tile of the same color
+    pub duplicate_group_colors: bool,
+    /// The rules for each of the special jokers, `J1` to `J3`
+    pub special_jokers: [JokerRules; SPECIAL_JOKERS as usize],
+}
+
+/// What a kind of joker can stand in for, and the penalty for being
+/// left holding it. By default special jokers play just like the
+/// plain joker, so they can be told apart on the board.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct JokerRules {
+    /// Whether it can stand in for a tile in a run
+    pub in_runs: bool,
+    /// Whether it can stand in for a tile in a group
+    pub in_groups: bool,
+    pub penalty: u8,
+}
+
+impl JokerRules {
+    /// The plain joker, which can go anywhere and costs 30 points
+    pub fn plain() -> JokerRules {
+        JokerRules {
+            in_runs: true,
+            in_groups: true,
+            penalty: 30,
+        }
+    }
+}
+
+impl RuleSet {
+    /// The standard rules
+    pub fn standard() -> RuleSet {
+        RuleSet {
+            min_meld_size: 3,
+            max_group_size: 4,
+            max_run_length: 13,
+            min_naturals: 2,
+            max_jokers: None,
+            wrap_around: false,
+            adjacent_jokers: true,
+            duplicate_group_colors: false,
+            special_jokers: [JokerRules::plain(); SPECIAL_JOKERS as usize],
+        }
+    }
+
+    /// The rules for the given kind of joker, or `None` if the tile
+    /// isn't a joker
+    pub fn joker_rules(&self, tile: &Tile) -> Option<JokerRules> {
+        match tile {
+            Tile::Joker => Some(JokerRules::plain()),
+            Tile::Special(n) => usize::from(*n)
+                .checked_sub(1)
+                .and_then(|n| self.special_jokers.get(n))
+                .cloned(),
+            Tile::Number(..) => None,
+        }
+    }
+
+    /// The penalty for holding this tile at the end of a game, taking
+    /// the value of special jokers into account.
+    pub fn penalty(&self, tile: &Tile) -> u8 {
+        match self.joker_rules(tile) {
+            Some(rules) => rules.penalty,
+            None => tile.penalty(),
+        }
+    }
+
+    /// Change a single rule by name, as given on the command line,
+    /// such as `wrap-around=true` or `min-naturals=1`. Special jokers
+    /// are set up with `joker1=runs`, `joker1=groups` or `joker1=any`
+    /// for where they can go, and `joker1-penalty=50`.
+    ///
+    /// Settings which would leave melds without natural tiles, or
+    /// with a maximum below `min-meld-size`, are refused and leave the
+    /// rules as they were, so raise a maximum before the minimum.
+    pub fn set(&mut self, setting: &str) -> Result<(), RuleError> {
+        let old = *self;
+        self.set_unchecked(setting)?;
+        if self.min_meld_size < 2
+            || self.min_naturals == 0
+            || self.max_group_size < self.min_meld_size
+            || self.max_run_length < self.min_meld_size
+        {
+            *self = old;
+            return Err(RuleError::OutOfRange(String::from(setting)));
+        }
+        Ok(())
+    }
+
+    fn set_unchecked(&mut self, setting: &str) -> Result<(), RuleError> {
+        let invalid = || RuleError::InvalidSetting(String::from(setting));
+        let mut parts = setting.splitn(2, '=');
+        let name = parts.next().ok_or_else(invalid)?;
+        let value = parts.next().ok_or_else(invalid)?;
+        let number = || value.parse::<u8>().map_err(|_| invalid());
+        let flag = || match value {
+            "true" | "yes" | "on" => Ok(true),
+            "false" | "no" | "off" => Ok(false),
+            _ => Err(invalid()),
+        };
+        match name {
+            "min-meld-size" => self.min_meld_size = number()?,
+            "max-group-size" => self.max_group_size = number()?,
+            "max-run-length" => self.max_run_length = number()?,
+            "min-naturals" => self.min_naturals = number()?,
+            "max-jokers" => {
+                self.max_jokers = match value {
+                    "none" => None,
+                    _ => Some(number()?),
+                }
+            }
+            "wrap-around" => self.wrap_around = flag()?,
+            "adjacent-jokers" => self.adjacent_jokers = flag()?,
+            "duplicate-group-colors" => self.duplicate_group_colors = flag()?,
+            _ if name.starts_with("joker") => {
+                let mut parts = name["joker".len()..].splitn(2, '-');
+                let joker = parts
+                    .next()
+                    .and_then(|n| n.parse::<usize>().ok())
+                    .and_then(|n| n.checked_sub(1))
+                    .and_then(|n| self.special_jokers.get_mut(n))
+                    .ok_or_else(|| RuleError::UnknownRule(String::from(name)))?;
+                match parts.next() {
+                    None => {
+                        let (in_runs, in_groups) = match value {
+                            "runs" => (true, false),
+                            "groups" => (false, true),
+                            "any" => (true, true),
+                            _ => return Err(invalid()),
+                        };
+                        joker.in_runs = in_runs;
+                        joker.in_groups = in_groups;
+                    }
+                    Some("penalty") => joker.penalty = number()?,
+                    Some(_) => return Err(RuleError::UnknownRule(String::from(name))),
+                }
+            }
+            _ => return Err(RuleError::UnknownRule(String::from(name))),
+        }
+        Ok(())
+    }
+}
+
+impl Default for RuleSet {
+    fn default() -> RuleSet {
+        RuleSet::standard()
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum RuleError {
+    UnknownRule(String),
+    /// Not of the form `name=value`, or the value doesn't fit the rule
+    InvalidSetting(String),
+    /// A meld size or count which would make every meld invalid, or
+    /// allow melds without any natural tiles
+    OutOfRange(String),
+}
+
+impl std::fmt::Display for RuleError {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            RuleError::UnknownRule(name) => write!(fmt, "Unknown rule {}", name),
+            RuleError::InvalidSetting(s) => write!(fmt, "Invalid rule setting {}, expected name=value", s),
+            RuleError::OutOfRange(s) => write!(fmt, "Rule setting {} is out of range", s),
+        }
+    }
+}
+
+impl std::error::Error for RuleError {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_set() {
+        let mut rules = RuleSet::standard();
+        rules.set("wrap-around=true").unwrap();
+        rules.set("min-naturals=1").unwrap();
+        rules.set("max-jokers=1").unwrap();
+        assert!(rules.wrap_around);
+        assert_eq!(rules.min_naturals, 1);
+        assert_eq!(rules.max_jokers, Some(1));
+        rules.set("max-jokers=none").unwrap();
+        assert_eq!(rules.max_jokers, None);
+    }
+
+    #[test]
+    fn test_set_special_jokers() {
+        let mut rules = RuleSet::standard();
+        rules.set("joker1=runs").unwrap();
+        rules.set("joker2-penalty=50").unwrap();
+        assert_eq!(
+            rules.joker_rules(&Tile::Special(1)),
+            Some(JokerRules { in_runs: true, in_groups: false, penalty: 30 })
+        );
+        assert_eq!(rules.penalty(&Tile::Special(2)), 50);
+        assert_eq!(rules.penalty(&Tile::Joker), 30);
+        assert_eq!(rules.set("joker4=runs"), Err(RuleError::UnknownRule(String::from("joker4"))));
+        assert_eq!(
+            rules.set("joker1=sometimes"),
+            Err(RuleError::InvalidSetting(String::from("joker1=sometimes")))
+        );
+    }
+
+    #[test]
+    fn test_set_invalid() {
+        let mut rules = RuleSet::standard();
+        assert_eq!(rules.set("foo=1"), Err(RuleError::UnknownRule(String::from("foo"))));
+        assert_eq!(
+            rules.set("wrap-around"),
+            Err(RuleError::InvalidSetting(String::from("wrap-around")))
+        );
+        assert_eq!(
+            rules.set("min-naturals=lots"),
+            Err(RuleError::InvalidSetting(String::from("min-naturals=lots")))
+        );
+        assert_eq!(rules, RuleSet::standard());
+    }
+
+    #[test]
+    fn test_set_out_of_range() {
+        let mut rules = RuleSet::standard();
+        for setting in &[
+            "min-meld-size=0",
+            "min-meld-size=1",
+            "min-naturals=0",
+            "max-group-size=2",
+            "max-run-length=2",
+            "min-meld-size=5",
+        ] {
+            assert_eq!(rules.set(setting), Err(RuleError::OutOfRange(String::from(*setting))));
+            assert_eq!(rules, RuleSet::standard());
+        }
+        rules.set("max-group-size=5").unwrap();
+        rules.set("min-meld-size=5").unwrap();
+        assert_eq!(rules.set("max-run-length=4"), Err(RuleError::OutOfRange(String::from("max-run-length=4"))));
+        assert_eq!(
+            RuleError::OutOfRange(String::from("min-naturals=0")).to_string(),
+            "Rule setting min-naturals=0 is out of range"
+        );
+    }
+}
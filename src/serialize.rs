@@ -0,0 +1,239 @@
+//! Serialization with the `serde` feature. The shape is meant to
+//! stay stable so other tools can rely on it. In JSON:
+//!
+//! * A `Color` is its lowercase name, such as `"red"`.
+//! * A `Tile` is written as in the standard notation, such as `"7R"`,
+//!   `"J"` for a joker or `"J1"` for a special joker.
+//! * `Tiles` are a list of the tiles present with their counts, in
+//!   the same order as they are displayed, such as
+//!   `[{"tile": "7R", "count": 2}, {"tile": "J", "count": 1}]`.
+//! * A `Meld` is a list of its tiles in the order they are laid out.
+//!   Tiles played by jokers also say which tile they stand in for,
+//!   such as `[{"tile": "6R"}, {"tile": "J", "as": "7R"}, {"tile": "8R"}]`.
+//! * A `Solution` is an object with its `combos`, a list of melds,
+//!   and the number of `leftover_jokers`.
+
+use serde::de::Error;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use super::meld::{Meld, MeldKind};
+use super::tiles::*;
+
+impl Serialize for Tile {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for Tile {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Tile, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        // Tiles outside the standard set can be serialized, but the
+        // rest of the crate can't handle them, so they aren't read
+        match parse_tile(&s, &Color::extended(), 255) {
+            None => Err(D::Error::custom(TilesError::InvalidTileString(s))),
+            Some(tile) if !tile.is_standard() => Err(D::Error::custom(TilesError::NotInTileSet(tile))),
+            Some(tile) => Ok(tile),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TileCount {
+    tile: Tile,
+    count: u8,
+}
+
+impl Serialize for Tiles {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let counts: Vec<TileCount> = Tile::all()
+            .map(|tile| TileCount { tile, count: self.get_count(&tile) })
+            .filter(|entry| entry.count > 0)
+            .collect();
+        counts.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Tiles {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Tiles, D::Error> {
+        let mut tiles = Tiles::new();
+        for entry in Vec::<TileCount>::deserialize(deserializer)? {
+            for _ in 0..entry.count {
+                tiles.add_tile(&entry.tile).map_err(D::Error::custom)?;
+            }
+        }
+        Ok(tiles)
+    }
+}
+
+/// A position in a meld, and the joker played there if any
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Position {
+    tile: Tile,
+    #[serde(rename = "as", default, skip_serializing_if = "Option::is_none")]
+    stands_for: Option<Tile>,
+}
+
+impl Serialize for Meld {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut jokers = self.joker_tiles().into_iter();
+        let positions: Vec<Position> = self
+            .layout()
+            .into_iter()
+            .map(|(tile, is_joker)| {
+                if is_joker {
+                    Position { tile: jokers.next().unwrap(), stands_for: Some(tile) }
+                } else {
+                    Position { tile, stands_for: None }
+                }
+            })
+            .collect();
+        positions.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Meld {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Meld, D::Error> {
+        let positions = Vec::<Position>::deserialize(deserializer)?;
+        let mut ranks = vec![];
+        let mut colors = vec![];
+        let mut jokers = Tiles::new();
+        let mut specials = Tiles::new();
+        for position in &positions {
+            let tile = match (position.tile, position.stands_for) {
+                (tile, None) => tile,
+                (joker, Some(tile)) if joker.is_joker() => {
+                    jokers.add_tile(&tile).map_err(D::Error::custom)?;
+                    if let Tile::Special(_) = joker {
+                        specials.add_tile(&joker).map_err(D::Error::custom)?;
+                    }
+                    tile
+                }
+                (tile, Some(_)) => return Err(D::Error::custom(format!("{} is not a joker", tile))),
+            };
+            match tile {
+                Tile::Number(rank, color) => {
+                    ranks.push(rank);
+                    colors.push(color);
+                }
+                Tile::Joker | Tile::Special(_) => {
+                    return Err(D::Error::custom(format!("{} does not say which tile it stands in for", tile)))
+                }
+            }
+        }
+
+        let (rank, color) = match (ranks.first(), colors.first()) {
+            (Some(rank), Some(color)) => (*rank, *color),
+            _ => return Err(D::Error::custom("a meld needs at least one tile")),
+        };
+        let kind = if colors.iter().all(|c| *c == color) && ranks.windows(2).all(|w| w[1] == w[0] % 13 + 1) {
+            MeldKind::Run { color, start: rank, len: ranks.len() as u8 }
+        } else if ranks.iter().all(|r| *r == rank) {
+            colors.sort();
+            MeldKind::Group { rank, colors }
+        } else {
+            return Err(D::Error::custom("the tiles are neither a run nor a group"));
+        };
+        Ok(Meld { kind, jokers, specials })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use super::super::solve::*;
+    use super::super::board::Board;
+    use super::super::rules::RuleSet;
+    use serde_json::json;
+
+    fn round_trip<T>(value: &T) -> T
+    where
+        T: Serialize + for<'de> Deserialize<'de>,
+    {
+        serde_json::from_str(&serde_json::to_string(value).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_shape() {
+        assert_eq!(serde_json::to_value(Color::Orange).unwrap(), json!("orange"));
+        assert_eq!(serde_json::to_value(Tile::Number(7, Color::Red)).unwrap(), json!("7R"));
+        assert_eq!(serde_json::to_value(Tile::Special(2)).unwrap(), json!("J2"));
+
+        let tiles: Tiles = "7R 7R J 1B".parse().unwrap();
+        assert_eq!(
+            serde_json::to_value(tiles).unwrap(),
+            json!([{"tile": "1B", "count": 1}, {"tile": "7R", "count": 2}, {"tile": "J", "count": 1}])
+        );
+
+        let board: Board = "5B 5U 5O J2 | 6R J 8R".parse().unwrap();
+        let solution = Solution { combos: board.melds, leftover_jokers: 0 };
+        assert_eq!(
+            serde_json::to_value(&solution).unwrap(),
+            json!({
+                "combos": [
+                    [{"tile": "5B"}, {"tile": "5U"}, {"tile": "5O"}, {"tile": "J2", "as": "5R"}],
+                    [{"tile": "6R"}, {"tile": "J", "as": "7R"}, {"tile": "8R"}],
+                ],
+                "leftover_jokers": 0,
+            })
+        );
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for color in Color::extended().iter() {
+            assert_eq!(&round_trip(color), color);
+        }
+        for tile in Tile::all() {
+            assert_eq!(round_trip(&tile), tile);
+        }
+
+        let tiles: Tiles = "1R 2R 3R 4R J J 5B 5U 5O 5O 11R 12R 13R".parse().unwrap();
+        assert_eq!(round_trip(&tiles), tiles);
+        assert_eq!(round_trip(&Tiles::new()), Tiles::new());
+        let solutions = solve(tiles);
+        assert!(!solutions.is_empty());
+        for solution in &solutions {
+            assert_eq!(&round_trip(solution), solution);
+        }
+
+        let mut options = SolveOptions::default();
+        options.rules.set("wrap-around=true").unwrap();
+        let solutions = solve_with("12R 13R J1 2R".parse().unwrap(), &options).solutions;
+        assert!(!solutions.is_empty());
+        for solution in &solutions {
+            assert_eq!(&round_trip(solution), solution);
+        }
+    }
+
+    #[test]
+    fn test_invalid() {
+        fn helper<T: for<'de> Deserialize<'de>>(json: &str) -> bool {
+            serde_json::from_str::<T>(json).is_err()
+        }
+        assert!(helper::<Color>(r#""yellow""#));
+        assert!(helper::<Tile>(r#""7X""#));
+        assert!(helper::<Tile>(r#""5G""#));
+        assert!(helper::<Tile>(r#""14R""#));
+        assert!(helper::<Tile>(r#""J4""#));
+        assert!(helper::<Tiles>(r#"[{"tile": "7R", "count": 3}]"#));
+        assert!(helper::<Tiles>(r#"[{"tile": "14R", "count": 1}]"#));
+        assert!(helper::<Tiles>(r#"[{"tile": "7R", "count": 1, "extra": 1}]"#));
+        assert!(helper::<Meld>(r#"[]"#));
+        assert!(helper::<Meld>(r#"[{"tile": "J"}, {"tile": "2R"}]"#));
+        assert!(helper::<Meld>(r#"[{"tile": "1R", "as": "2R"}]"#));
+        assert!(helper::<Meld>(r#"[{"tile": "J", "as": "J1"}]"#));
+        assert!(helper::<Meld>(r#"[{"tile": "1R"}, {"tile": "2B"}, {"tile": "3R"}]"#));
+        assert!(helper::<Meld>(r#"[{"tile": "5G"}, {"tile": "6G"}, {"tile": "7G"}]"#));
+        assert!(helper::<Meld>(r#"[{"tile": "J", "as": "5G"}, {"tile": "5R"}, {"tile": "5B"}]"#));
+        assert!(helper::<Solution>(r#"{"combos": []}"#));
+    }
+
+    #[test]
+    fn test_group_order() {
+        let meld: Meld = serde_json::from_str(r#"[{"tile": "5R"}, {"tile": "J", "as": "5O"}, {"tile": "5B"}]"#).unwrap();
+        assert!(Meld::classify_with(&meld.tiles(), &RuleSet::standard()).contains(&meld));
+        assert_eq!(meld.to_string(), "5B J=5O 5R");
+    }
+}
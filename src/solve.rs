@@ -1,171 +1,1496 @@
+use std::collections::{HashMap, HashSet};
 use std::fmt::Display;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
 use super::tiles::*;
+use super::meld::*;
+use super::rules::RuleSet;
 
 /// A solution for a Rummikub board. Note that leftover_jokers should
 /// be 0 for a valid solution according to the rules. However, many
 /// players will want to know if there are extra jokers left over in
 /// some cases, and so we include those solutions as valid.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Solution {
-    pub combos: Vec<Tiles>,
+    pub combos: Vec<Meld>,
     pub leftover_jokers: u8,
 }
 
 impl Solution {
-    fn new<'a>(mut list: Option<&'a SolutionList<'a>>, leftover_jokers: u8) -> Solution {
+    fn new(mut list: Option<&Rc<MeldList>>, leftover_jokers: u8) -> Solution {
         let mut combos = vec![];
         while let Some(sol) = list {
-            list = sol.rest;
-            combos.push(sol.current);
+            list = sol.rest.as_ref();
+            combos.push(sol.current.clone());
         }
         Solution { combos, leftover_jokers }
     }
+
+    /// An equivalent solution in a canonical form, so that solutions
+    /// which only differ in the order of their combos, or in what the
+    /// jokers stand in for, compare equal. Each combo is normalized
+    /// with `Meld::normalized`, and the combos are sorted.
+    pub fn canonical(&self) -> Solution {
+        let mut combos: Vec<Meld> = self.combos.iter().map(Meld::normalized).collect();
+        combos.sort();
+        Solution {
+            combos,
+            leftover_jokers: self.leftover_jokers,
+        }
+    }
+
+    /// Check that every combo is a valid meld under the standard
+    /// rules, see `validate_with`.
+    pub fn validate(&self) -> Result<(), SolutionError> {
+        self.validate_with(&RuleSet::standard())
+    }
+
+    /// Check that every combo is a valid meld under `rules`, and
+    /// that between them they use no more than two of any tile.
+    pub fn validate_with(&self, rules: &RuleSet) -> Result<(), SolutionError> {
+        let mut tiles = Tiles::new();
+        for (index, combo) in self.combos.iter().enumerate() {
+            combo.check(rules).map_err(|error| SolutionError::InvalidMeld { index, error })?;
+            for (tile, count) in combo.tiles().iter() {
+                for _ in 0..count {
+                    tiles.add_tile(&tile)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Why a solution isn't valid, see `Solution::validate`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SolutionError {
+    /// The combo at `index` breaks the rules
+    InvalidMeld { index: usize, error: MeldError },
+    /// The combos use more of a tile than there are
+    Tiles(TilesError),
+}
+
+impl Display for SolutionError {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            SolutionError::InvalidMeld { index, error } => write!(fmt, "Combo {}: {}", index + 1, error),
+            SolutionError::Tiles(e) => e.fmt(fmt),
+        }
+    }
+}
+
+impl std::error::Error for SolutionError {}
+
+impl From<TilesError> for SolutionError {
+    fn from(e: TilesError) -> SolutionError {
+        SolutionError::Tiles(e)
+    }
 }
 
 impl Display for Solution {
     fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
-        for tiles in self.combos.iter() {
-            write!(fmt, "({}), ", tiles)?;
+        for combo in self.combos.iter() {
+            write!(fmt, "({}), ", combo)?;
         }
         write!(fmt, "leftover jokers: {}", self.leftover_jokers)
     }
 }
 
+/// The melds placed so far during a search, most recent first. The
+/// tail is shared between all of the states that branched off from
+/// it.
 #[derive(Debug)]
-struct SolutionList<'a> {
-    rest: Option<&'a SolutionList<'a>>,
-    current: Tiles,
+struct MeldList {
+    rest: Option<Rc<MeldList>>,
+    current: Meld,
+}
+
+/// What to aim for when choosing which tiles to play from a rack.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Objective {
+    /// Play as many tiles as possible.
+    TileCount,
+    /// Play the highest total face value, which is the same as
+    /// leaving the smallest penalty in hand.
+    PointSum,
+    /// Get rid of the highest tiles first: a single tile of a higher
+    /// rank is worth more than any number of lower tiles. Jokers are
+    /// the highest of all.
+    HighTiles,
+}
+
+impl Objective {
+    /// Score the given set of played tiles. Higher is better.
+    pub fn score(&self, played: &Tiles) -> u64 {
+        self.score_with(played, &RuleSet::standard())
+    }
+
+    /// Like `score`, with special jokers worth the penalty given by
+    /// `rules`.
+    pub fn score_with(&self, played: &Tiles, rules: &RuleSet) -> u64 {
+        Tile::all()
+            .map(|tile| {
+                let count = u64::from(played.get_count(&tile));
+                let weight = match self {
+                    Objective::TileCount => 1,
+                    Objective::PointSum => u64::from(rules.penalty(&tile)),
+                    Objective::HighTiles => {
+                        // There are at most 8 tiles of a single
+                        // rank, so each rank is worth more than all
+                        // lower ranks combined.
+                        let rank = match tile {
+                            Tile::Joker | Tile::Special(_) => 14,
+                            Tile::Number(rank, _) => rank,
+                        };
+                        9u64.pow(u32::from(rank) - 1)
+                    }
+                };
+                count * weight
+            })
+            .sum()
+    }
+}
+
+/// A solution which plays tiles from a rack onto a board. Every tile
+/// from the board is placed, along with the tiles in `played`. The
+/// tiles in `remaining` stay in the player's hand. `score` is the
+/// value of `played` for the `Objective` used.
+#[derive(Debug, Clone)]
+pub struct RackSolution {
+    pub solution: Solution,
+    pub played: Tiles,
+    pub remaining: Tiles,
+    pub score: u64,
+}
+
+impl Display for RackSolution {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            fmt,
+            "{}; played: ({}), remaining: ({}), score: {}",
+            self.solution, self.played, self.remaining, self.score
+        )
+    }
 }
 
 /// Generate a vector of all possible solutions for the given set of tiles.
 pub fn solve(tiles: Tiles) -> Vec<Solution> {
+    solve_iter(tiles).collect()
+}
+
+/// Generate all solutions for the given set of tiles, exploring the
+/// first few branches of the search on a thread pool. The solutions
+/// are the same as from `solve`, and in the same order.
+#[cfg(feature = "parallel")]
+pub fn solve_parallel(tiles: Tiles) -> Vec<Solution> {
+    use rayon::prelude::*;
+
+    let branches = split(tiles, rayon::current_num_threads() * 4);
+    let results: Vec<Vec<Solution>> = branches
+        .into_par_iter()
+        .map(|branch| match branch {
+            Branch::Done(solution) => vec![solution],
+            Branch::Open { board, next, fresh, prefix } => {
+                Search::from_state(State { next, fresh, ..State::start(board, Tiles::new()) })
+                    .map(|(mut solution, _)| {
+                        solution.combos.extend(prefix.iter().cloned());
+                        solution
+                    })
+                    .collect()
+            }
+        })
+        .collect();
+    results.into_iter().flatten().collect()
+}
+
+/// A piece of the search handed to a worker thread. `State` holds
+/// its melds in an `Rc`, so open branches carry the melds placed so
+/// far as a plain list instead.
+#[cfg(feature = "parallel")]
+enum Branch {
+    Done(Solution),
+    Open {
+        board: Tiles,
+        next: Tile,
+        fresh: bool,
+        prefix: Vec<Meld>,
+    },
+}
+
+/// Expand the search breadth first until there are at least `wanted`
+/// open branches, keeping the branches in the order a sequential
+/// search would visit them.
+#[cfg(feature = "parallel")]
+fn split(tiles: Tiles, wanted: usize) -> Vec<Branch> {
+    let mut states = vec![State::start(tiles, Tiles::new())];
+    let mut done: Vec<Option<Solution>> = vec![None];
+
+    // Never split deeper than this, so a board with few branches
+    // doesn't get expanded in full on one thread
+    for _ in 0..8 {
+        if states.len() >= wanted {
+            break;
+        }
+        let mut next_states = vec![];
+        let mut next_done = vec![];
+        let mut expanded = false;
+        for (mut state, solution) in states.into_iter().zip(done) {
+            if solution.is_some() {
+                next_states.push(state);
+                next_done.push(solution);
+                continue;
+            }
+            match state.advance() {
+                Some((solution, _)) => {
+                    next_states.push(state);
+                    next_done.push(Some(solution));
+                }
+                None => {
+                    expanded = true;
+                    for child in state.children(&RuleSet::standard()) {
+                        next_states.push(child);
+                        next_done.push(None);
+                    }
+                }
+            }
+        }
+        states = next_states;
+        done = next_done;
+        if !expanded {
+            break;
+        }
+    }
+
+    states
+        .into_iter()
+        .zip(done)
+        .map(|(state, solution)| match solution {
+            Some(solution) => Branch::Done(solution),
+            None => Branch::Open {
+                board: state.board,
+                next: state.next,
+                fresh: state.fresh,
+                prefix: Solution::new(state.melds.as_ref(), 0).combos,
+            },
+        })
+        .collect()
+}
+
+/// Generate the distinct solutions for the given set of tiles. When
+/// there are two copies of a tile, or jokers, `solve` can find the
+/// same arrangement more than once, for example by placing the same
+/// two runs in a different order. This keeps only the first of each
+/// set of solutions with the same `Solution::canonical` form, and
+/// returns them in canonical form.
+pub fn solve_distinct(tiles: Tiles) -> Vec<Solution> {
+    let mut seen = HashSet::new();
     let mut res = vec![];
-    solve_loop(&mut res, tiles, Tile::min_value(), None);
+    for solution in solve_iter(tiles) {
+        let solution = solution.canonical();
+        if seen.insert(solution.clone()) {
+            res.push(solution);
+        }
+    }
     res
 }
 
-fn solve_loop<'a>(results: &mut Vec<Solution>, tiles: Tiles, mut next: Tile, rest: Option<&'a SolutionList<'a>>) {
-    loop {
-        let next_count = tiles.get_count(&next);
-        if next_count == 0 {
-            match next.next() {
-                Some(x) => {
-                    next = x;
-                    continue;
+/// Lazily generate all possible solutions for the given set of
+/// tiles, in the same order as `solve`. Solutions are only searched
+/// for as they are requested, so taking the first few solutions of a
+/// large board is cheap.
+pub fn solve_iter(tiles: Tiles) -> SolveIter {
+    SolveIter(Search::new(tiles, Tiles::new()))
+}
+
+/// Iterator over the solutions for a board, see `solve_iter`.
+pub struct SolveIter(Search);
+
+impl SolveIter {
+    /// Why the search stopped early, if it did. Only meaningful once
+    /// the iterator has returned `None`.
+    pub fn stopped(&self) -> Option<StopReason> {
+        self.0.stopped
+    }
+}
+
+impl Iterator for SolveIter {
+    type Item = Solution;
+
+    fn next(&mut self) -> Option<Solution> {
+        self.0.next().map(|(solution, _)| solution)
+    }
+}
+
+/// How `solve_with` should search: which rules melds have to follow,
+/// and limits on how much work it may do. The default uses the
+/// standard rules and places no limits at all.
+#[derive(Debug, Clone, Default)]
+pub struct SolveOptions {
+    /// The rules which melds have to follow
+    pub rules: RuleSet,
+    /// Stop once this many solutions have been found
+    pub max_solutions: Option<usize>,
+    /// Stop once this time has passed. `Instant` is not available on
+    /// wasm32, so the web UI uses `max_nodes` instead.
+    pub deadline: Option<Instant>,
+    /// Stop after exploring this many search states
+    pub max_nodes: Option<u64>,
+    /// Stop as soon as possible once this is set, for example from
+    /// another thread
+    pub cancel: Option<Arc<AtomicBool>>,
+}
+
+impl SolveOptions {
+    /// Check whether any of the limits, other than `max_solutions`,
+    /// have been reached once `nodes` states have been explored.
+    pub(crate) fn check_limits(&self, nodes: u64) -> Option<StopReason> {
+        if let Some(max) = self.max_nodes {
+            if nodes > max {
+                return Some(StopReason::NodeBudget);
+            }
+        }
+        // Reading the clock on every state would slow the search down
+        // noticeably, so only check the clock and the flag every so
+        // often.
+        if nodes % 1024 == 1 {
+            if let Some(cancel) = &self.cancel {
+                if cancel.load(Ordering::Relaxed) {
+                    return Some(StopReason::Cancelled);
                 }
-                None => {
-                    results.push(Solution::new(rest, 0));
-                    break;
+            }
+            if let Some(deadline) = self.deadline {
+                if Instant::now() >= deadline {
+                    return Some(StopReason::Deadline);
                 }
             }
         }
+        None
+    }
+}
 
+/// Why a search finished before exploring every possibility.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopReason {
+    MaxSolutions,
+    Deadline,
+    NodeBudget,
+    Cancelled,
+}
+
+impl Display for StopReason {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            StopReason::MaxSolutions => write!(fmt, "reached the maximum number of solutions"),
+            StopReason::Deadline => write!(fmt, "ran out of time"),
+            StopReason::NodeBudget => write!(fmt, "explored the maximum number of states"),
+            StopReason::Cancelled => write!(fmt, "cancelled"),
+        }
+    }
+}
+
+/// The solutions found by `solve_with`, and whether there may be
+/// more.
+#[derive(Debug, Clone)]
+pub struct SolveResult {
+    pub solutions: Vec<Solution>,
+    /// `None` if the search was exhaustive
+    pub stopped: Option<StopReason>,
+}
+
+impl SolveResult {
+    /// Are `solutions` all of the solutions for the board?
+    pub fn is_exhaustive(&self) -> bool {
+        self.stopped.is_none()
+    }
+}
+
+/// The best plays found by `solve_rack_with_options`, and whether
+/// there may be better ones.
+#[derive(Debug, Clone)]
+pub struct RackResult {
+    pub solutions: Vec<RackSolution>,
+    /// `None` if the search was exhaustive
+    pub stopped: Option<StopReason>,
+}
+
+impl RackResult {
+    /// Are `solutions` all of the best plays from the rack?
+    pub fn is_exhaustive(&self) -> bool {
+        self.stopped.is_none()
+    }
+}
+
+/// Generate solutions for the given set of tiles, in the same order
+/// as `solve`, until one of the limits in `options` is reached.
+/// Reaching `max_solutions` counts as truncating the search, even if
+/// there turn out to be no more solutions.
+pub fn solve_with(tiles: Tiles, options: &SolveOptions) -> SolveResult {
+    let mut iter = solve_iter_with(tiles, options);
+    let solutions = iter.by_ref().collect();
+    SolveResult {
+        solutions,
+        stopped: iter.stopped(),
+    }
+}
+
+/// Like `solve_iter`, but stops once one of the limits in `options`
+/// is reached. Use `SolveIter::stopped` afterwards to find out
+/// whether the search was exhaustive.
+pub fn solve_iter_with(tiles: Tiles, options: &SolveOptions) -> SolveIter {
+    let mut search = Search::new(tiles, Tiles::new());
+    search.options = options.clone();
+    SolveIter(search)
+}
+
+/// Find all arrangements which place every tile from the board along
+/// with the largest possible number of tiles from the rack.
+pub fn solve_rack(board: Tiles, rack: Tiles) -> Vec<RackSolution> {
+    solve_rack_with(board, rack, Objective::TileCount)
+}
+
+/// Find all arrangements which place every tile from the board along
+/// with the tiles from the rack that score best for the given
+/// objective.
+///
+/// Arrangements which leave fewer jokers from the board unplaced are
+/// always preferred, regardless of which rack tiles they play.
+pub fn solve_rack_with(board: Tiles, rack: Tiles, objective: Objective) -> Vec<RackSolution> {
+    solve_rack_with_rules(board, rack, objective, &RuleSet::standard())
+}
+
+/// Like `solve_rack_with`, with melds following the given rules and
+/// special jokers scored with their own penalties.
+pub fn solve_rack_with_rules(board: Tiles, rack: Tiles, objective: Objective, rules: &RuleSet) -> Vec<RackSolution> {
+    let options = SolveOptions { rules: *rules, ..SolveOptions::default() };
+    solve_rack_with_options(board, rack, objective, &options).solutions
+}
+
+/// Like `solve_rack_with_rules`, but stops once one of the limits in
+/// `options` is reached, returning the best plays found so far.
+/// `options.max_solutions` limits the arrangements looked at, not the
+/// best plays returned.
+pub fn solve_rack_with_options(board: Tiles, rack: Tiles, objective: Objective, options: &SolveOptions) -> RackResult {
+    let rules = &options.rules;
+    let mut res: Vec<RackSolution> = vec![];
+    let mut best = None;
+    let mut search = Search::new(board, rack);
+    search.options = options.clone();
+    for (solution, remaining) in &mut search {
+        let played = rack.checked_sub(&remaining).unwrap();
+        let score = objective.score_with(&played, rules);
+
+        let key = (std::cmp::Reverse(solution.leftover_jokers), score);
+        match best {
+            Some(best) if key < best => continue,
+            Some(best) if key == best => (),
+            _ => {
+                best = Some(key);
+                res.clear();
+            }
+        }
+        res.push(RackSolution {
+            solution,
+            played,
+            remaining,
+            score,
+        });
+    }
+    RackResult {
+        solutions: res,
+        stopped: search.stopped,
+    }
+}
+
+/// Find a single best play from the rack for the given objective, if
+/// the board can be solved at all.
+pub fn best_rack_play(board: Tiles, rack: Tiles, objective: Objective) -> Option<RackSolution> {
+    solve_rack_with(board, rack, objective).into_iter().next()
+}
+
+/// Count the solutions for the given set of tiles without generating
+/// them. This is the same as `solve(tiles).len()`, including the
+/// solutions with leftover jokers. Every numbered tile of the full set
+/// is counted in well under a second, though each joker makes it
+/// several times slower.
+pub fn count_solutions(tiles: Tiles) -> u128 {
+    count_solutions_with(tiles, &RuleSet::standard())
+}
+
+/// Like `count_solutions`, with the given rules. With wrap-around,
+/// special jokers or fewer than two natural tiles in a meld, this
+/// follows the search in `solve` instead, remembering the count for
+/// each set of remaining tiles, which is far slower for large boards.
+pub fn count_solutions_with(tiles: Tiles, rules: &RuleSet) -> u128 {
+    if Counter::handles(&tiles, rules) {
+        return Counter::new(tiles, rules, false).count();
+    }
+    fn helper(memo: &mut HashMap<(Tiles, Tile), u128>, rules: &RuleSet, tiles: Tiles, next: Tile) -> u128 {
+        let next = match first_remaining(&tiles, next) {
+            None => return 1,
+            Some(next) => next,
+        };
+        let (rank, color) = match next {
+            // the leftover jokers solution
+            Tile::Joker | Tile::Special(_) => return 1,
+            Tile::Number(rank, color) => (rank, color),
+        };
+        if let Some(count) = memo.get(&(tiles, next)) {
+            return *count;
+        }
+        let count = candidates(tiles, Tiles::new(), rank, color, rules)
+            .into_iter()
+            .map(|candidate| helper(memo, rules, candidate.board, next))
+            .sum();
+        memo.insert((tiles, next), count);
+        count
+    }
+    helper(&mut HashMap::new(), rules, tiles, Tile::min_value())
+}
+
+/// Can all of the given tiles be placed, without any leftover jokers?
+pub fn is_solvable(tiles: Tiles) -> bool {
+    is_solvable_with(tiles, &RuleSet::standard())
+}
+
+/// Like `is_solvable`, with the given rules.
+pub fn is_solvable_with(tiles: Tiles, rules: &RuleSet) -> bool {
+    if Counter::handles(&tiles, rules) {
+        return Counter::new(tiles, rules, true).count() > 0;
+    }
+    fn helper(memo: &mut HashMap<(Tiles, Tile), bool>, rules: &RuleSet, tiles: Tiles, next: Tile) -> bool {
+        let next = match first_remaining(&tiles, next) {
+            None => return true,
+            Some(next) => next,
+        };
         let (rank, color) = match next {
-            Tile::Joker => {
-                // not quite a solution, but we want to know about it
-                results.push(Solution::new(rest, tiles.get_count(&next)));
+            Tile::Joker | Tile::Special(_) => return false,
+            Tile::Number(rank, color) => (rank, color),
+        };
+        if let Some(solvable) = memo.get(&(tiles, next)) {
+            return *solvable;
+        }
+        let solvable = candidates(tiles, Tiles::new(), rank, color, rules)
+            .into_iter()
+            .any(|candidate| helper(memo, rules, candidate.board, next));
+        memo.insert((tiles, next), solvable);
+        solvable
+    }
+    helper(&mut HashMap::new(), rules, tiles, Tile::min_value())
+}
+
+/// A run which has been started but may carry on, while counting
+/// solutions a rank at a time. Only as much of its length and makeup
+/// is kept as the rules need, so that runs which can be finished in
+/// the same ways are the same.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct OpenRun {
+    color: Color,
+    len: u8,
+    naturals: u8,
+    jokers: u8,
+    last_joker: bool,
+    /// Started with jokers below its lowest tile, so it has to reach
+    /// 13
+    to_13: bool,
+}
+
+// There are millions of these in memo keys for the full set, so
+// they're hashed as a single number
+impl std::hash::Hash for OpenRun {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        let flags = u32::from(self.last_joker) | u32::from(self.to_13) << 1;
+        let fields = [self.color as u32, u32::from(self.len), u32::from(self.naturals), u32::from(self.jokers)];
+        state.write_u32(fields.iter().fold(flags, |packed, field| packed << 6 | field));
+    }
+}
+
+/// The rank being placed, the color within it or 4 while carrying
+/// on runs, the copies of each color left at this rank, the jokers
+/// left, the open runs and the runs still to be carried on.
+type CountKey = (u8, usize, [u8; 4], u8, Vec<OpenRun>, Vec<OpenRun>);
+
+/// Counts the solutions the search in `solve` would find, without
+/// following it. The search picks the whole of a run as soon as it
+/// reaches the run's lowest tile, which takes tiles far above the
+/// lowest tile left, so there are a vast number of states once the
+/// board is large. Here runs are only carried on to the next rank
+/// when it is reached instead, so each state is just the tiles left
+/// at one rank, the jokers left and the runs still open.
+///
+/// Open runs take the tiles at each rank before anything else, in
+/// the order they were started, which is the order the search in
+/// `solve` would have taken them. Jokers are only counted once used,
+/// rather than when their meld is picked, but either way a set of
+/// melds can be made when there are enough jokers for all of them.
+///
+/// This only follows the search for plain jokers without wrap-around,
+/// and with at least two natural tiles in every meld, so that a run
+/// never uses the same tiles as a group.
+struct Counter<'a> {
+    tiles: Tiles,
+    rules: &'a RuleSet,
+    /// Only find out whether there are solutions without leftover
+    /// jokers, so stop at the first one
+    solvable: bool,
+    memo: HashMap<CountKey, u128>,
+}
+
+impl<'a> Counter<'a> {
+    fn handles(tiles: &Tiles, rules: &RuleSet) -> bool {
+        !tiles.has_special_jokers() && !rules.wrap_around && rules.min_naturals >= 2
+    }
+
+    fn new(tiles: Tiles, rules: &'a RuleSet, solvable: bool) -> Counter<'a> {
+        Counter {
+            tiles,
+            rules,
+            solvable,
+            memo: HashMap::new(),
+        }
+    }
+
+    fn count(&mut self) -> u128 {
+        let jokers = self.tiles.get_count(&Tile::Joker);
+        self.next_rank(1, &[], 0, self.counts(1), jokers, vec![])
+    }
+
+    /// The copies of each color at the given rank, none past 13
+    fn counts(&self, rank: u8) -> [u8; 4] {
+        let mut counts = [0; 4];
+        if rank <= 13 {
+            for color in Color::all() {
+                counts[color as usize] = self.tiles.get_count(&Tile::Number(rank, color));
+            }
+        }
+        counts
+    }
+
+    /// Keep only what matters about a run for finishing it
+    fn normalize(&self, mut run: OpenRun) -> OpenRun {
+        let rules = self.rules;
+        if rules.max_run_length >= 13 {
+            run.len = run.len.min(rules.min_meld_size);
+        }
+        run.naturals = run.naturals.min(rules.min_naturals);
+        run.jokers = run.jokers.min(rules.max_jokers.unwrap_or(0));
+        run.last_joker &= !rules.adjacent_jokers;
+        run
+    }
+
+    /// Can the run end at the last rank it has a tile for? `end` is
+    /// set once every rank has been placed.
+    fn can_finish(&self, run: &OpenRun, end: bool) -> bool {
+        (end || !run.to_13) && run.len >= self.rules.min_meld_size && run.naturals >= self.rules.min_naturals
+    }
+
+    /// Carry each of the open runs `runs[i..]` on to `rank`, or end
+    /// them, then place the rest of the tiles at that rank.
+    fn next_rank(&mut self, rank: u8, runs: &[OpenRun], i: usize, counts: [u8; 4], jokers: u8, open: Vec<OpenRun>) -> u128 {
+        let run = match runs.get(i) {
+            Some(run) => *run,
+            None if rank > 13 => return if !self.solvable || jokers == 0 { 1 } else { 0 },
+            None => return self.place(rank, 0, counts, jokers, open),
+        };
+        // The runs of each color are only remembered together
+        if i > 0 && runs[i - 1].color == run.color {
+            return self.carry_on(rank, runs, i, counts, jokers, open);
+        }
+        let key = (rank, 4, counts, jokers, open, runs[i..].to_vec());
+        if let Some(count) = self.memo.get(&key) {
+            return *count;
+        }
+        let count = self.carry_on(rank, runs, i, counts, jokers, key.4.clone());
+        self.memo.insert(key, count);
+        count
+    }
+
+    /// Carry the open run `runs[i]` on to `rank`, or end it
+    fn carry_on(&mut self, rank: u8, runs: &[OpenRun], i: usize, counts: [u8; 4], jokers: u8, open: Vec<OpenRun>) -> u128 {
+        let mut run = runs[i];
+        let mut count = 0;
+        if self.can_finish(&run, rank > 13) {
+            count += self.next_rank(rank, runs, i + 1, counts, jokers, open.clone());
+        }
+        if (self.solvable && count > 0) || rank > 13 || run.len >= self.rules.max_run_length {
+            return count;
+        }
+        let mut counts = counts;
+        let mut jokers = jokers;
+        let color = run.color as usize;
+        if counts[color] > 0 {
+            counts[color] -= 1;
+            run.naturals += 1;
+            run.last_joker = false;
+        } else if jokers > 0 {
+            jokers -= 1;
+            run.jokers += 1;
+            if run.last_joker || self.rules.max_jokers.map(|max| run.jokers > max) == Some(true) {
+                return count;
+            }
+            run.last_joker = !self.rules.adjacent_jokers;
+        } else {
+            return count;
+        }
+        run.len += 1;
+        let mut open = open;
+        open.push(self.normalize(run));
+        count + self.next_rank(rank, runs, i + 1, counts, jokers, open)
+    }
+
+    /// Place the tiles left at `rank`, from the given color on
+    fn place(&mut self, rank: u8, color: usize, counts: [u8; 4], jokers: u8, open: Vec<OpenRun>) -> u128 {
+        if color == 4 {
+            return self.next_rank(rank + 1, &open, 0, self.counts(rank + 1), jokers, vec![]);
+        }
+        if counts[color] == 0 {
+            return self.place(rank, color + 1, counts, jokers, open);
+        }
+        let key = (rank, color, counts, jokers, open, vec![]);
+        if let Some(count) = self.memo.get(&key) {
+            return *count;
+        }
+        let (_, _, _, _, open, _) = key;
+        let tile_color = Color::all().nth(color).unwrap();
+        let mut taken = counts;
+        taken[color] -= 1;
+
+        // A run starting here, which comes after the open runs of the
+        // same color as it was started later
+        let run = OpenRun { color: tile_color, len: 1, naturals: 1, jokers: 0, last_joker: false, to_13: false };
+        let mut children = vec![(taken, jokers, self.with_run(&open, run))];
+
+        // Runs with jokers for lower tiles, which have to reach 13
+        let max_len = self.rules.max_run_length.min(13);
+        for used in 1..rank.min(jokers + 1) {
+            let len = 14 - rank + used;
+            if len > max_len {
                 break;
-            },
+            }
+            if len < self.rules.min_meld_size
+                || (used > 1 && !self.rules.adjacent_jokers)
+                || self.rules.max_jokers.map(|max| used > max) == Some(true)
+            {
+                continue;
+            }
+            let run = OpenRun { color: tile_color, len: used + 1, naturals: 1, jokers: used, last_joker: false, to_13: true };
+            let run = self.normalize(run);
+            children.push((taken, jokers - used, self.with_run(&open, run)));
+        }
+
+        for (left, used) in group_candidates(counts, tile_color, jokers, self.rules) {
+            children.push((left, jokers - used, open.clone()));
+        }
+
+        let mut count = 0;
+        for (left, jokers, open) in children {
+            count += self.place(rank, color, left, jokers, open);
+            if self.solvable && count > 0 {
+                break;
+            }
+        }
+
+        self.memo.insert((rank, color, counts, jokers, open, vec![]), count);
+        count
+    }
+
+    fn with_run(&self, open: &[OpenRun], run: OpenRun) -> Vec<OpenRun> {
+        let mut open = open.to_vec();
+        let at = open.iter().position(|other| other.color > run.color).unwrap_or(open.len());
+        open.insert(at, run);
+        open
+    }
+}
+
+/// The groups `candidates` would find for the lowest tile, with the
+/// given color, when `counts` are the copies of each color at its
+/// rank. Returns the counts left after each group, and the jokers it
+/// uses.
+fn group_candidates(counts: [u8; 4], color: Color, jokers: u8, rules: &RuleSet) -> Vec<([u8; 4], u8)> {
+    let mut res: Vec<([u8; 4], u8)> = vec![];
+    let mut test_group = |colors: &[Color]| {
+        let mut left = counts;
+        let mut used = 0;
+        for other in colors {
+            if left[*other as usize] > 0 {
+                left[*other as usize] -= 1;
+            } else if used < jokers {
+                used += 1;
+            } else {
+                return;
+            }
+        }
+        let too_many_jokers = rules.max_jokers.map(|max| used > max) == Some(true);
+        if colors.len() - usize::from(used) < usize::from(rules.min_naturals) || too_many_jokers {
+            return;
+        }
+        if !res.contains(&(left, used)) {
+            res.push((left, used));
+        }
+    };
+
+    each_group(color, jokers, rules, &mut |colors| test_group(&colors));
+    res
+}
+
+/// Find the smallest set of tiles which have to be taken off the
+/// board for the rest of it to be solvable without leftover jokers.
+/// This is empty if the board is already solvable. If there are
+/// several sets of the same size, only one of them is returned.
+pub fn unplaceable_tiles(tiles: Tiles) -> Tiles {
+    unplaceable_tiles_with(tiles, &RuleSet::standard())
+}
+
+/// Like `unplaceable_tiles`, with the given rules.
+pub fn unplaceable_tiles_with(tiles: Tiles, rules: &RuleSet) -> Tiles {
+    let options = SolveOptions { rules: *rules, ..SolveOptions::default() };
+    unplaceable_tiles_with_options(tiles, &options).expect("Search stopped without limits")
+}
+
+/// Like `unplaceable_tiles_with`, but gives up once one of the limits
+/// in `options` is reached, returning why. `options.max_solutions`
+/// doesn't apply, as there is only one answer.
+pub fn unplaceable_tiles_with_options(tiles: Tiles, options: &SolveOptions) -> Result<Tiles, StopReason> {
+    /// The number of tiles which have to be removed
+    fn helper(
+        memo: &mut HashMap<(Tiles, Tile), u32>,
+        options: &SolveOptions,
+        nodes: &mut u64,
+        tiles: Tiles,
+        next: Tile,
+    ) -> Result<u32, StopReason> {
+        let next = match first_remaining(&tiles, next) {
+            None => return Ok(0),
+            Some(next) => next,
+        };
+        let (rank, color) = match next {
+            // every joker still left over has to go
+            Tile::Joker | Tile::Special(_) => return Ok(u32::from(tiles.get_joker_count())),
+            Tile::Number(rank, color) => (rank, color),
+        };
+        if let Some(removed) = memo.get(&(tiles, next)) {
+            return Ok(*removed);
+        }
+        *nodes += 1;
+        if let Some(reason) = options.check_limits(*nodes) {
+            return Err(reason);
+        }
+        let mut without = tiles;
+        without.set_count(&next, tiles.get_count(&next) - 1);
+        let mut removed = 1 + helper(memo, options, nodes, without, next)?;
+        for candidate in candidates(tiles, Tiles::new(), rank, color, &options.rules) {
+            removed = removed.min(helper(memo, options, nodes, candidate.board, next)?);
+        }
+        memo.insert((tiles, next), removed);
+        Ok(removed)
+    }
+
+    // Walk through the search again, following the choices which
+    // lead to the fewest removed tiles.
+    let rules = &options.rules;
+    let mut memo = HashMap::new();
+    let mut nodes = 0;
+    let mut res = Tiles::new();
+    let mut tiles = tiles;
+    let mut next = Tile::min_value();
+    loop {
+        let removed = helper(&mut memo, options, &mut nodes, tiles, next)?;
+        next = match first_remaining(&tiles, next) {
+            None => break,
+            Some(next) => next,
+        };
+        let (rank, color) = match next {
+            Tile::Joker | Tile::Special(_) => {
+                for joker in Tile::jokers() {
+                    res.set_count(&joker, tiles.get_count(&joker));
+                }
+                break;
+            }
+            Tile::Number(rank, color) => (rank, color),
+        };
+        let mut placed = None;
+        for candidate in candidates(tiles, Tiles::new(), rank, color, rules) {
+            if helper(&mut memo, options, &mut nodes, candidate.board, next)? == removed {
+                placed = Some(candidate);
+                break;
+            }
+        }
+        match placed {
+            Some(candidate) => tiles = candidate.board,
+            None => {
+                res.add_tile(&next).unwrap();
+                tiles.set_count(&next, tiles.get_count(&next) - 1);
+            }
+        }
+    }
+    Ok(res)
+}
+
+/// Find the first tile, starting at `next`, which we still have.
+fn first_remaining(tiles: &Tiles, mut next: Tile) -> Option<Tile> {
+    while tiles.get_count(&next) == 0 {
+        next = next.next()?;
+    }
+    Some(next)
+}
+
+/// The minimum value of an opening meld under the standard rules.
+pub const OPENING_THRESHOLD: u64 = 30;
+
+/// The best opening melds found for a rack.
+#[derive(Debug, Clone)]
+pub struct Openings {
+    /// The qualifying opening with the highest value
+    pub highest: RackSolution,
+    /// The qualifying opening which plays the fewest tiles
+    pub fewest_tiles: RackSolution,
+}
+
+/// Find opening melds for a player who has not yet gone down. These
+/// are played from the rack only, and must be worth at least
+/// `threshold` points in total, with jokers valued as the tile they
+/// stand in for. The `score` of each returned `RackSolution` is the
+/// value of the opening. Returns `None` if no opening is possible.
+pub fn solve_opening(rack: Tiles, threshold: u64) -> Option<Openings> {
+    solve_opening_with(rack, threshold, &SolveOptions::default()).openings
+}
+
+/// The openings found by `solve_opening_with`, and whether there may
+/// be better ones.
+#[derive(Debug, Clone)]
+pub struct OpeningResult {
+    /// `None` if no opening was found
+    pub openings: Option<Openings>,
+    /// `None` if the search was exhaustive
+    pub stopped: Option<StopReason>,
+}
+
+/// Like `solve_opening`, with melds following `options.rules`, but
+/// stops once one of the limits in `options` is reached, returning
+/// the best openings found so far. `options.max_solutions` limits
+/// the arrangements looked at, not the openings returned.
+pub fn solve_opening_with(rack: Tiles, threshold: u64, options: &SolveOptions) -> OpeningResult {
+    let mut highest: Option<RackSolution> = None;
+    let mut fewest_tiles: Option<RackSolution> = None;
+    let mut search = Search::new(Tiles::new(), rack);
+    search.options = options.clone();
+    for (solution, remaining) in &mut search {
+        let score = solution.combos.iter().map(Meld::value).sum();
+        if score < threshold {
+            continue;
+        }
+        let tile_count = rack.get_total_count() - remaining.get_total_count();
+
+        let is_higher = match &highest {
+            None => true,
+            Some(best) => {
+                (score, std::cmp::Reverse(tile_count))
+                    > (best.score, std::cmp::Reverse(best.played.get_total_count()))
+            }
+        };
+        let is_fewer = match &fewest_tiles {
+            None => true,
+            Some(best) => {
+                (std::cmp::Reverse(tile_count), score)
+                    > (std::cmp::Reverse(best.played.get_total_count()), best.score)
+            }
+        };
+        if !is_higher && !is_fewer {
+            continue;
+        }
+
+        let played = rack.checked_sub(&remaining).unwrap();
+        let opening = RackSolution {
+            solution,
+            played,
+            remaining,
+            score,
+        };
+        if is_higher {
+            highest = Some(opening.clone());
+        }
+        if is_fewer {
+            fewest_tiles = Some(opening);
+        }
+    }
+    let openings = match (highest, fewest_tiles) {
+        (Some(highest), Some(fewest_tiles)) => Some(Openings { highest, fewest_tiles }),
+        _ => None,
+    };
+    OpeningResult {
+        openings,
+        stopped: search.stopped,
+    }
+}
+
+/// A state in the search for solutions. Every tile in `board` must
+/// be placed, while tiles in `rack` may be placed or moved to `hand`.
+/// `fresh` indicates that we haven't yet decided how many copies of
+/// `next` to keep in hand.
+struct State {
+    board: Tiles,
+    rack: Tiles,
+    hand: Tiles,
+    next: Tile,
+    fresh: bool,
+    melds: Option<Rc<MeldList>>,
+}
+
+impl State {
+    fn start(board: Tiles, rack: Tiles) -> State {
+        State {
+            board,
+            rack,
+            hand: Tiles::new(),
+            next: Tile::min_value(),
+            fresh: true,
+            melds: None,
+        }
+    }
+
+    /// Move `next` on to the first tile which remains to be placed.
+    /// If there is nothing left which can be placed, return the
+    /// finished solution along with the tiles kept in hand.
+    fn advance(&mut self) -> Option<(Solution, Tiles)> {
+        while self.board.get_count(&self.next) + self.rack.get_count(&self.next) == 0 {
+            match self.next.next() {
+                Some(x) => {
+                    self.next = x;
+                    self.fresh = true;
+                }
+                None => return Some((Solution::new(self.melds.as_ref(), 0), self.hand)),
+            }
+        }
+
+        if self.next.is_joker() {
+            // not quite a solution, but we want to know about it.
+            // Jokers from the rack simply stay in hand.
+            let leftover_jokers = self.board.get_joker_count();
+            let mut hand = self.hand;
+            for joker in Tile::jokers() {
+                hand.set_count(&joker, self.rack.get_count(&joker));
+            }
+            return Some((Solution::new(self.melds.as_ref(), leftover_jokers), hand));
+        }
+
+        None
+    }
+
+    fn key(&self) -> StateKey {
+        (self.board, self.rack, self.next, self.fresh)
+    }
+
+    /// The states reached by keeping or placing `next`, in the order
+    /// they should be explored. Must only be called after `advance`.
+    fn children(&self, rules: &RuleSet) -> Vec<State> {
+        let (rank, color) = match self.next {
+            Tile::Joker | Tile::Special(_) => unreachable!(),
             Tile::Number(rank, color) => (rank, color),
         };
 
-        let mut test_combo = |mut combo: Tiles| {
-            let mut tiles = tiles;
+        let mut children = vec![];
+
+        if self.fresh {
+            // Decide up front how many copies from the rack to keep,
+            // so that we don't find the same solution twice by
+            // keeping different copies.
+            let rack_count = self.rack.get_count(&self.next);
+            for keep in 1..=rack_count {
+                let mut rack = self.rack;
+                let mut hand = self.hand;
+                rack.set_count(&self.next, rack_count - keep);
+                hand.set_count(&self.next, keep);
+                children.push(State {
+                    rack,
+                    hand,
+                    fresh: false,
+                    melds: self.melds.clone(),
+                    ..*self
+                });
+            }
+        }
+
+        for candidate in candidates(self.board, self.rack, rank, color, rules) {
+            children.push(State {
+                board: candidate.board,
+                rack: candidate.rack,
+                hand: self.hand,
+                next: self.next,
+                fresh: false,
+                melds: Some(Rc::new(MeldList {
+                    current: candidate.meld,
+                    rest: self.melds.clone(),
+                })),
+            });
+        }
+
+        children
+    }
+}
+
+/// The parts of a `State` which determine whether any solutions can
+/// be found from it.
+type StateKey = (Tiles, Tiles, Tile, bool);
+
+/// An entry on the search stack.
+enum Frame {
+    /// A state still to be explored
+    Explore(State),
+    /// Everything reachable from the state with the given key has
+    /// been explored. `found` is the number of solutions which had
+    /// been produced before we started exploring it.
+    Done { key: StateKey, found: u64 },
+}
+
+/// Depth-first search for solutions, producing each solution together
+/// with the rack tiles which were kept in hand. Rather than
+/// recursing, we keep an explicit stack of the states still to be
+/// explored, which lets us stop at any point.
+///
+/// The same remaining tiles are often reached by placing melds in a
+/// different order. We remember every state which turned out to have
+/// no solutions, so that dead ends are only ever explored once.
+struct Search {
+    stack: Vec<Frame>,
+    found: u64,
+    dead: Option<HashSet<StateKey>>,
+    options: SolveOptions,
+    nodes: u64,
+    stopped: Option<StopReason>,
+}
+
+impl Search {
+    fn new(board: Tiles, rack: Tiles) -> Search {
+        Search::from_state(State::start(board, rack))
+    }
 
-            // must have at least 2 natural tiles per combo
-            let mut natural = 0;
+    /// Search for the solutions reachable from the given state.
+    fn from_state(state: State) -> Search {
+        Search {
+            stack: vec![Frame::Explore(state)],
+            found: 0,
+            dead: Some(HashSet::new()),
+            options: SolveOptions::default(),
+            nodes: 0,
+            stopped: None,
+        }
+    }
 
-            for tile in Tile::all_no_jokers() {
-                assert!(combo.get_count(&tile) <= 1);
-                if combo.get_count(&tile) == 0 { continue };
+    /// Count another explored state, and check whether any of the
+    /// limits in `options` have been reached.
+    fn check_limits(&mut self) -> Option<StopReason> {
+        self.nodes += 1;
+        self.options.check_limits(self.nodes)
+    }
 
-                match tiles.get_count(&tile) {
-                    0 => {
-                        // check for a joker
-                        match tiles.get_count(&Tile::Joker) {
-                            0 => return false, // nothing, give up
-                            count => {
-                                tiles.set_count(&Tile::Joker, count - 1);
-                                combo.add_tile(&Tile::Joker).unwrap();
-                                combo.set_count(&tile, 0);
-                            }
+    /// A plain backtracking search, without remembering dead ends.
+    /// Only useful for comparing performance.
+    #[cfg(test)]
+    fn without_memo(board: Tiles, rack: Tiles) -> Search {
+        Search {
+            dead: None,
+            ..Search::new(board, rack)
+        }
+    }
+}
+
+impl Iterator for Search {
+    type Item = (Solution, Tiles);
+
+    fn next(&mut self) -> Option<(Solution, Tiles)> {
+        if let Some(max) = self.options.max_solutions {
+            if self.found >= max as u64 && !self.stack.is_empty() {
+                self.stopped = Some(StopReason::MaxSolutions);
+                self.stack.clear();
+                return None;
+            }
+        }
+        while let Some(frame) = self.stack.pop() {
+            let mut state = match frame {
+                Frame::Explore(state) => state,
+                Frame::Done { key, found } => {
+                    if found == self.found {
+                        if let Some(dead) = self.dead.as_mut() {
+                            dead.insert(key);
                         }
                     }
-                    count => {
-                        tiles.set_count(&tile, count - 1);
-                        natural += 1;
+                    continue;
+                }
+            };
+
+            if let Some(reason) = self.check_limits() {
+                self.stopped = Some(reason);
+                self.stack.clear();
+                return None;
+            }
+
+            if let Some((solution, hand)) = state.advance() {
+                self.found += 1;
+                return Some((solution, hand));
+            }
+
+            let key = state.key();
+            match &self.dead {
+                Some(dead) if dead.contains(&key) => continue,
+                _ => (),
+            }
+            self.stack.push(Frame::Done { key, found: self.found });
+
+            // Explore the children in order
+            self.stack.extend(state.children(&self.options.rules).into_iter().rev().map(Frame::Explore));
+        }
+        None
+    }
+}
+
+/// A meld which can be placed, along with the tiles that are left
+/// over after placing it.
+struct Candidate {
+    meld: Meld,
+    board: Tiles,
+    rack: Tiles,
+}
+
+/// Pass the colors of each group which includes `color` and follows
+/// `rules` to `found`, sorted. Tiles of lower colors are used up
+/// already, so groups which would need more than `jokers` jokers for
+/// them may be left out early. Both `candidates` and the counter use this, so
+/// they always agree on which groups there are.
+fn each_group(color: Color, jokers: u8, rules: &RuleSet, found: &mut dyn FnMut(Vec<Color>)) {
+    let others: Vec<Color> = Color::all().filter(|other| *other != color).collect();
+    if rules.duplicate_group_colors {
+        // Up to two tiles of each color, as there are only two
+        // copies of each tile
+        let combinations = 3usize.pow(others.len() as u32 + 1);
+        for size in rules.min_meld_size..=rules.max_group_size {
+            for combination in 0..combinations {
+                let mut colors = vec![color];
+                let mut rest = combination;
+                for other in Color::all() {
+                    for _ in 0..rest % 3 {
+                        colors.push(other);
+                    }
+                    rest /= 3;
+                }
+                if colors.len() != usize::from(size) || colors.iter().filter(|c| **c == color).count() > 2 {
+                    continue;
+                }
+                colors.sort();
+                found(colors);
+            }
+        }
+    } else {
+        // Each subset of the other colors, largest bitmask first.
+        // Lower colors than this one are used up already, so will be
+        // played by jokers.
+        let lower_mask = (1u32 << others.iter().filter(|other| **other < color).count()) - 1;
+        let max_size = usize::from(rules.max_group_size).min(others.len() + 1);
+        for size in usize::from(rules.min_meld_size)..=max_size {
+            for mask in (0..1u32 << others.len()).rev() {
+                let lower = (mask & lower_mask).count_ones();
+                if mask.count_ones() as usize + 1 != size
+                    || lower > u32::from(jokers)
+                    || size - (lower as usize) < usize::from(rules.min_naturals)
+                {
+                    continue;
+                }
+                let mut colors = vec![color];
+                for (i, other) in others.iter().enumerate() {
+                    if mask & (1 << i) != 0 {
+                        colors.push(*other);
                     }
                 }
+                colors.sort();
+                found(colors);
+            }
+        }
+    }
+}
+
+/// Find all of the melds which contain the tile with the given rank
+/// and color, which must be the lowest tile remaining, and follow the
+/// given rules. Melds which use up exactly the same tiles as an
+/// earlier one, differing only in what the jokers stand in for, are
+/// left out.
+fn candidates(board: Tiles, rack: Tiles, rank: u8, color: Color, rules: &RuleSet) -> Vec<Candidate> {
+    let mut res: Vec<Candidate> = vec![];
+
+    // The kinds of joker which can go in runs, or in groups. Usually
+    // there are no special jokers, and this is just the plain joker.
+    let jokers_for = |in_runs: bool| -> Vec<Tile> {
+        Tile::jokers()
+            .filter(|joker| {
+                let joker_rules = rules.joker_rules(joker).unwrap();
+                if in_runs { joker_rules.in_runs } else { joker_rules.in_groups }
+            })
+            .collect()
+    };
+    let (run_vec, group_vec);
+    let (run_jokers, group_jokers): (&[Tile], &[Tile]) = if board.has_special_jokers() || rack.has_special_jokers() {
+        run_vec = jokers_for(true);
+        group_vec = jokers_for(false);
+        (&run_vec, &group_vec)
+    } else {
+        (&[Tile::Joker], &[Tile::Joker])
+    };
+
+    // Returns false if there aren't the tiles to make this meld, in
+    // which case there's no point trying a longer run.
+    let mut test_combo = |kind: MeldKind| {
+        let is_run = match kind {
+            MeldKind::Run { .. } => true,
+            MeldKind::Group { .. } => false,
+        };
+        let kinds = if is_run { run_jokers } else { group_jokers };
+        let available: u8 = kinds.iter().map(|joker| board.get_count(joker) + rack.get_count(joker)).sum();
+
+        let mut board = board;
+        let mut rack = rack;
+
+        // Take a tile from the board if possible, since those
+        // must be placed anyway, and otherwise from the rack.
+        let mut take = |tile: &Tile| {
+            for tiles in [&mut board, &mut rack].iter_mut() {
+                let count = tiles.get_count(tile);
+                if count > 0 {
+                    tiles.set_count(tile, count - 1);
+                    return true;
+                }
             }
-            if natural < 2 { return false; }
+            false
+        };
+
+        let mut natural = 0;
+        let mut joker_count = 0;
+        let mut adjacent_jokers = false;
+        let mut previous_joker = false;
+        let mut jokers = Tiles::new();
 
-            let solution = SolutionList {
-                current: combo,
-                rest,
+        for tile in kind.positions() {
+            if take(&tile) {
+                natural += 1;
+                previous_joker = false;
+            } else if joker_count < available {
+                jokers.add_tile(&tile).unwrap();
+                joker_count += 1;
+                adjacent_jokers |= previous_joker && is_run;
+                previous_joker = true;
+            } else {
+                return false; // nothing, give up
+            }
+        }
+
+        let too_many_jokers = match rules.max_jokers {
+            None => false,
+            Some(max) => joker_count > max,
+        };
+        if natural < rules.min_naturals || too_many_jokers || (adjacent_jokers && !rules.adjacent_jokers) {
+            return true;
+        }
+        // Only clone the meld's shape when the jokers can be chosen in
+        // more than one way
+        let mut kind = Some(kind);
+        take_jokers(board, rack, joker_count, kinds, Tiles::new(), &mut |board, rack, specials| {
+            if res.iter().any(|candidate| candidate.board == board && candidate.rack == rack) {
+                return;
+            }
+            let kind = match kind.take() {
+                Some(kind) => kind,
+                None => res[res.len() - 1].meld.kind.clone(),
             };
-            solve_loop(results, tiles, next, Some(&solution));
-            true
-        };
-
-        // runs
-        if rank <= 11 {
-            let mut combo = Tiles::new();
-            combo.set_count(&next, 1);
-            combo.set_count(&Tile::Number(rank + 1, color), 1);
-            for rank in rank + 2 ..= 13 {
-                combo.set_count(&Tile::Number(rank, color), 1);
-                if !test_combo(combo) { break }
-            }
-        } else if rank == 12 {
-            // special case: if we have a 12, 13, and joker, we can
-            // make a run, but the above code will miss it
-            let mut combo = Tiles::new();
-            combo.set_count(&next, 1);
-            combo.set_count(&Tile::Number(11, color), 1);
-            combo.set_count(&Tile::Number(13, color), 1);
-            test_combo(combo);
-        }
-
-        // triples/quads
-        let mut helper = |colors: &[Color]| {
-            let mut combo = Tiles::new();
-            combo.set_count(&next, 1);
-            for color in colors {
-                combo.set_count(&Tile::Number(rank, *color), 1);
-            }
-            test_combo(combo);
-        };
-        match color {
-            Color::Black => {
-                helper(&[Color::Orange, Color::Red]);
-                helper(&[Color::Blue, Color::Red]);
-                helper(&[Color::Blue, Color::Orange]);
-                helper(&[Color::Blue, Color::Orange, Color::Red]);
-            },
-            Color::Blue => {
-                helper(&[Color::Orange, Color::Red]);
-            },
-            Color::Orange => {
-                // Seems like we shouldn't have to do anything here,
-                // since we know we have, at most, an orange and a
-                // red. However, there may still be a joker. So: test
-                // out with a blue and a red, and the test_combo
-                // closure above will replace the blue with a joker
-                // (if available).
-                //
-                // We could replace blue with black, it will do the
-                // same thing.
-                helper(&[Color::Blue, Color::Red]);
-            },
-            Color::Red => ()
+            res.push(Candidate {
+                meld: Meld { kind, jokers, specials },
+                board,
+                rack,
+            });
+        });
+        true
+    };
+
+    let available_jokers = board.get_joker_count() + rack.get_joker_count();
+
+    // runs
+    let max_len = rules.max_run_length.min(13);
+    let max_here = if rules.wrap_around { max_len } else { max_len.min(14 - rank) };
+    for len in rules.min_meld_size..=max_here {
+        if !test_combo(MeldKind::Run { color, start: rank, len }) { break }
+    }
+    if rules.wrap_around {
+        // Runs which start with a higher tile, and wrap around to
+        // this one. Any jokers at the start of the run could just as
+        // well go at the end, so the first tile has to be natural.
+        for start in rank + 1..=13 {
+            let first = Tile::Number(start, color);
+            if board.get_count(&first) + rack.get_count(&first) == 0 {
+                continue;
+            }
+            for len in rules.min_meld_size.max(14 - start + rank)..=max_len {
+                if !test_combo(MeldKind::Run { color, start, len }) { break }
+            }
+        }
+    } else {
+        // Jokers standing in for lower tiles: these could go at the
+        // end of the run instead, unless the run already reaches 13.
+        // For example, we have a 12, 13, and joker.
+        for jokers in 1..rank.min(available_jokers + 1) {
+            let len = 14 - rank + jokers;
+            if len > max_len {
+                break;
+            }
+            if len >= rules.min_meld_size && !test_combo(MeldKind::Run { color, start: rank - jokers, len }) {
+                break;
+            }
         }
+    }
+
+    // triples/quads
+    each_group(color, available_jokers, rules, &mut |colors| {
+        test_combo(MeldKind::Group { rank, colors });
+    });
 
-        break;
+    res
+}
+
+/// Call `f` for every way of taking `count` jokers of the given
+/// kinds, taking each kind from the board before the rack, with the
+/// board and rack left afterwards and the special jokers taken.
+/// Plain jokers are used up first.
+fn take_jokers(
+    board: Tiles,
+    rack: Tiles,
+    count: u8,
+    kinds: &[Tile],
+    specials: Tiles,
+    f: &mut impl FnMut(Tiles, Tiles, Tiles),
+) {
+    let (joker, rest) = match kinds.split_first() {
+        _ if count == 0 => return f(board, rack, specials),
+        None => return,
+        Some(split) => split,
+    };
+    let on_board = board.get_count(joker);
+    let on_rack = rack.get_count(joker);
+    for taken in (0..=count.min(on_board + on_rack)).rev() {
+        let from_board = taken.min(on_board);
+        let mut board = board;
+        let mut rack = rack;
+        let mut specials = specials;
+        board.set_count(joker, on_board - from_board);
+        rack.set_count(joker, on_rack - (taken - from_board));
+        if *joker != Tile::Joker {
+            specials.set_count(joker, taken);
+        }
+        take_jokers(board, rack, count - taken, rest, specials, f);
     }
 }
 
+
 #[cfg(test)]
-mod test {
+pub(crate) mod test {
     use super::*;
+    use crate::test::Bencher;
 
     fn count_solutions(tiles: Tiles) -> usize {
         solve(tiles).len()
@@ -248,4 +1573,781 @@ mod test {
     fn test_joker_and_12_run() {
         assert_eq!(count_solutions("12R 13R J".parse().unwrap()), 1);
     }
+
+    fn rack_helper(board: &str, rack: &str) -> Vec<RackSolution> {
+        solve_rack(board.parse().unwrap(), rack.parse().unwrap())
+    }
+
+    #[test]
+    fn test_rack_empty() {
+        let sols = rack_helper("1R 2R 3R", "");
+        assert_eq!(sols.len(), 1);
+        assert_eq!(sols[0].played, Tiles::new());
+        assert_eq!(sols[0].remaining, Tiles::new());
+    }
+
+    #[test]
+    fn test_rack_nothing_playable() {
+        let sols = rack_helper("1R 2R 3R", "7B 9U");
+        assert_eq!(sols.len(), 1);
+        assert_eq!(sols[0].played, Tiles::new());
+        assert_eq!(sols[0].remaining, "7B 9U".parse().unwrap());
+    }
+
+    #[test]
+    fn test_rack_unsolvable_board() {
+        assert_eq!(rack_helper("1R 2R", "7B 9U").len(), 0);
+    }
+
+    #[test]
+    fn test_rack_extends_run() {
+        let sols = rack_helper("1R 2R 3R", "4R 5R 9U");
+        assert_eq!(sols.len(), 1);
+        for sol in sols {
+            assert_eq!(sol.played, "4R 5R".parse().unwrap());
+            assert_eq!(sol.remaining, "9U".parse().unwrap());
+            assert_eq!(sol.solution.leftover_jokers, 0);
+        }
+    }
+
+    #[test]
+    fn test_rack_completes_board() {
+        let sols = rack_helper("1R 2R", "3R 3R");
+        assert_eq!(sols.len(), 1);
+        assert_eq!(sols[0].played, "3R".parse().unwrap());
+        assert_eq!(sols[0].remaining, "3R".parse().unwrap());
+    }
+
+    #[test]
+    fn test_rack_only() {
+        let sols = rack_helper("", "5B 5U 5O 8R 9R J");
+        assert_eq!(sols.len(), 1);
+        assert_eq!(sols[0].played, "5B 5U 5O 8R 9R J".parse().unwrap());
+    }
+
+    #[test]
+    fn test_rack_prefers_placing_board_jokers() {
+        let sols = rack_helper("5B 5U J", "8R 9R 10R");
+        assert!(!sols.is_empty());
+        for sol in sols {
+            assert_eq!(sol.solution.leftover_jokers, 0);
+            assert_eq!(sol.remaining, Tiles::new());
+        }
+    }
+
+    #[test]
+    fn test_objective_scores() {
+        let played = "3R 4R 5R 13B J".parse().unwrap();
+        assert_eq!(Objective::TileCount.score(&played), 5);
+        assert_eq!(Objective::PointSum.score(&played), 3 + 4 + 5 + 13 + 30);
+        assert!(Objective::HighTiles.score(&"13B".parse().unwrap())
+                > Objective::HighTiles.score(&"12B 12B 12U 12U 12O 12O 12R 12R".parse().unwrap()));
+    }
+
+    #[test]
+    fn test_rack_point_sum() {
+        // The joker can either go with the low run or with the 13s
+        let board = Tiles::new();
+        let rack = "1B 2B 4B 5B 13U 13O J".parse().unwrap();
+
+        let sol = best_rack_play(board, rack, Objective::TileCount).unwrap();
+        assert_eq!(sol.played, "1B 2B 4B 5B J".parse().unwrap());
+        assert_eq!(sol.score, 5);
+
+        let sol = best_rack_play(board, rack, Objective::PointSum).unwrap();
+        assert_eq!(sol.played, "13U 13O J".parse().unwrap());
+        assert_eq!(sol.score, 13 + 13 + 30);
+    }
+
+    #[test]
+    fn test_rack_high_tiles() {
+        let board = Tiles::new();
+        let rack = "8B 9B 11B 12B 13U 13O J".parse().unwrap();
+
+        let sol = best_rack_play(board, rack, Objective::PointSum).unwrap();
+        assert_eq!(sol.played, "8B 9B 11B 12B J".parse().unwrap());
+        assert_eq!(sol.score, 8 + 9 + 11 + 12 + 30);
+
+        let sols = solve_rack_with(board, rack, Objective::HighTiles);
+        assert_eq!(sols.len(), 1);
+        assert_eq!(sols[0].played, "13U 13O J".parse().unwrap());
+    }
+
+    #[test]
+    fn test_combo_value_counts_jokers() {
+        let sols = solve("5R J 7R".parse().unwrap());
+        assert_eq!(sols.len(), 1);
+        assert_eq!(sols[0].combos[0].jokers, "6R".parse().unwrap());
+        assert_eq!(sols[0].combos[0].value(), 18);
+        assert_eq!(sols[0].combos[0].to_string(), "5R J=6R 7R");
+    }
+
+    #[test]
+    fn test_opening_too_small() {
+        assert!(solve_opening("1R 2R 3R 5B 5U 5O".parse().unwrap(), OPENING_THRESHOLD).is_none());
+    }
+
+    #[test]
+    fn test_opening_joker_value() {
+        // 9R J 11R is worth 30 with the joker as 10R
+        let openings = solve_opening("9R J 11R 2B".parse().unwrap(), OPENING_THRESHOLD).unwrap();
+        assert_eq!(openings.highest.score, 30);
+        assert_eq!(openings.highest.played, "9R J 11R".parse().unwrap());
+        assert_eq!(openings.fewest_tiles.played, "9R J 11R".parse().unwrap());
+    }
+
+    #[test]
+    fn test_opening_highest_and_fewest() {
+        let rack = "10B 10U 10O 1R 2R 3R 4R 5R 6R 7R".parse().unwrap();
+        let openings = solve_opening(rack, OPENING_THRESHOLD).unwrap();
+        assert_eq!(openings.highest.score, 30 + 28);
+        assert_eq!(openings.highest.remaining, Tiles::new());
+        assert_eq!(openings.fewest_tiles.score, 30);
+        assert_eq!(openings.fewest_tiles.played, "10B 10U 10O".parse().unwrap());
+    }
+
+    #[test]
+    fn test_opening_with_rules() {
+        let rack = "9R 10R 11R 12R 10B 10U 10O".parse().unwrap();
+        let openings = solve_opening(rack, OPENING_THRESHOLD).unwrap();
+        assert_eq!(openings.highest.score, 42 + 30);
+        let mut options = SolveOptions::default();
+        options.rules.set("max-run-length=3").unwrap();
+        let openings = solve_opening_with(rack, OPENING_THRESHOLD, &options).openings.unwrap();
+        assert_eq!(openings.highest.score, 33 + 30);
+        let mut options = SolveOptions::default();
+        options.rules.set("min-meld-size=4").unwrap();
+        let openings = solve_opening_with(rack, OPENING_THRESHOLD, &options).openings.unwrap();
+        assert_eq!(openings.highest.played, "9R 10R 11R 12R".parse().unwrap());
+        let res = solve_opening_with("10B 10U 10O".parse().unwrap(), OPENING_THRESHOLD, &options);
+        assert!(res.openings.is_none());
+        assert_eq!(res.stopped, None);
+    }
+
+    #[test]
+    fn test_opening_with_limits() {
+        let rack = "10B 10U 10O 1R 2R 3R 4R 5R 6R 7R".parse().unwrap();
+        let options = SolveOptions {
+            max_nodes: Some(3),
+            ..SolveOptions::default()
+        };
+        let res = solve_opening_with(rack, OPENING_THRESHOLD, &options);
+        assert_eq!(res.stopped, Some(StopReason::NodeBudget));
+        let options = SolveOptions {
+            deadline: Some(Instant::now()),
+            ..SolveOptions::default()
+        };
+        let res = solve_opening_with(rack, OPENING_THRESHOLD, &options);
+        assert_eq!(res.stopped, Some(StopReason::Deadline));
+        assert!(res.openings.is_none());
+    }
+
+    #[test]
+    fn test_opening_threshold() {
+        let rack = "10B 10U 10O 1R 2R 3R".parse().unwrap();
+        assert!(solve_opening(rack, 37).is_none());
+        let openings = solve_opening(rack, 36).unwrap();
+        assert_eq!(openings.fewest_tiles.remaining, Tiles::new());
+    }
+
+    /// Both copies of every tile, including the jokers
+    pub(crate) fn full_deck() -> Tiles {
+        let mut tiles = Tiles::new();
+        for tile in Tile::all_no_jokers() {
+            tiles.set_count(&tile, 2);
+        }
+        tiles.set_count(&Tile::Joker, 2);
+        tiles
+    }
+
+    #[test]
+    fn test_iter_matches_solve() {
+        let tiles: Tiles = "1R 2R 3R 4R 5R 6R 1B 1U 1O J J 7R 8R".parse().unwrap();
+        let expected: Vec<String> = solve(tiles).iter().map(Solution::to_string).collect();
+        let actual: Vec<String> = solve_iter(tiles).map(|sol| sol.to_string()).collect();
+        assert!(expected.len() > 1);
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn test_iter_full_deck_first_solutions() {
+        let sols: Vec<Solution> = solve_iter(full_deck()).take(5).collect();
+        assert_eq!(sols.len(), 5);
+        for sol in sols {
+            let placed: u8 = sol.combos.iter().map(|meld| meld.tiles().get_total_count()).sum();
+            assert_eq!(placed + sol.leftover_jokers, 106);
+        }
+    }
+
+    #[test]
+    fn test_count_matches_solve() {
+        for s in &["", "1R", "1R 2R 3R 4R 5r 6r", "1R j 1o", "12R 13R J",
+                   "1R 2R 3R 4R 5R 6R 1B 1U 1O J J 7R 8R",
+                   "1R 1R 2R 2R 3R 3R 1B 1U 1O 1O J"] {
+            let tiles = s.parse().unwrap();
+            assert_eq!(super::count_solutions(tiles), solve(tiles).len() as u128, "{}", s);
+        }
+    }
+
+    #[test]
+    fn test_is_solvable() {
+        assert!(is_solvable(Tiles::new()));
+        assert!(is_solvable("1R j 3r 4r".parse().unwrap()));
+        assert!(!is_solvable("1R 2R".parse().unwrap()));
+        assert!(is_solvable("1R 2R 3R J".parse().unwrap()));
+        // the only solution has a leftover joker
+        assert!(!is_solvable("J".parse().unwrap()));
+        assert!(!is_solvable("1R 1B 1U 1O J".parse().unwrap()));
+    }
+
+    /// Both copies of every tile up to the given rank, plus jokers
+    pub(crate) fn low_deck(max_rank: u8, jokers: u8) -> Tiles {
+        let mut tiles = Tiles::new();
+        for tile in Tile::all_no_jokers() {
+            match tile {
+                Tile::Number(rank, _) if rank <= max_rank => tiles.set_count(&tile, 2),
+                _ => (),
+            }
+        }
+        tiles.set_count(&Tile::Joker, jokers);
+        tiles
+    }
+
+    #[test]
+    fn test_count_larger_boards() {
+        for tiles in &[low_deck(4, 1), low_deck(3, 2)] {
+            assert_eq!(super::count_solutions(*tiles), solve(*tiles).len() as u128);
+        }
+        assert_eq!(super::count_solutions(low_deck(6, 0)), 521594);
+    }
+
+    /// Without jokers, `min-naturals=1` makes no difference to the
+    /// count, but has `count_solutions_with` fall back to the search
+    /// which remembers the count for each set of remaining tiles, so
+    /// the two ways of counting can be compared.
+    fn eager_count(tiles: Tiles) -> u128 {
+        let mut rules = RuleSet::standard();
+        rules.set("min-naturals=1").unwrap();
+        count_solutions_with(tiles, &rules)
+    }
+
+    #[test]
+    fn test_count_full_deck() {
+        for max_rank in 1..=6 {
+            assert_eq!(super::count_solutions(low_deck(max_rank, 0)), eager_count(low_deck(max_rank, 0)));
+        }
+        // The same as `eager_count` for the full deck, which takes
+        // several minutes even in a release build. The ranks 7 to 12
+        // give 11673941, 284304329, 7044332185, 174680547840,
+        // 4356292787492 and 109086016491223 both ways.
+        let mut tiles = full_deck();
+        tiles.set_count(&Tile::Joker, 0);
+        assert_eq!(super::count_solutions(tiles), 2734199299095196);
+    }
+
+    #[test]
+    fn test_count_with_rules() {
+        let settings: &[&[&str]] = &[
+            &["max-jokers=1"],
+            &["adjacent-jokers=false"],
+            &["max-run-length=4"],
+            &["duplicate-group-colors=true"],
+            &["max-group-size=5", "min-meld-size=4"],
+            &["min-naturals=1"],
+            &["wrap-around=true"],
+        ];
+        let boards: Vec<Tiles> = [
+            "1R 2R 3R 4R 5R 6R 1B 1U 1O J J 7R 8R",
+            "1R 1R 2R 2R 3R 3R 1B 1U 1O 1O J",
+            "1R 1B 1U 1O 2R 2B 2U 2O 3R 3B 3U 3O 3O J",
+            "11R 12R 13R 1R 2R J 13B 13O",
+        ]
+        .iter()
+        .map(|s| s.parse().unwrap())
+        .collect();
+        for setting in settings {
+            let mut rules = RuleSet::standard();
+            for rule in *setting {
+                rules.set(rule).unwrap();
+            }
+            let options = SolveOptions { rules, ..SolveOptions::default() };
+            for tiles in &boards {
+                let solutions = solve_with(*tiles, &options).solutions;
+                assert_eq!(count_solutions_with(*tiles, &rules), solutions.len() as u128, "{} {:?}", tiles, setting);
+                let solvable = solutions.iter().any(|solution| solution.leftover_jokers == 0);
+                assert_eq!(is_solvable_with(*tiles, &rules), solvable, "{} {:?}", tiles, setting);
+            }
+        }
+    }
+
+    #[test]
+    fn test_full_deck_solvable() {
+        assert!(is_solvable(full_deck()));
+        let mut tiles = full_deck();
+        tiles.set_count(&Tile::Number(13, Color::Red), 1);
+        tiles.set_count(&Tile::Joker, 0);
+        assert!(is_solvable(tiles));
+        // Without the other 13s or 12R, 13R can't be placed
+        for tile in &["J", "12R", "13B", "13U", "13O"] {
+            tiles.set_count(&tile.parse().unwrap(), 0);
+        }
+        assert!(!is_solvable(tiles));
+    }
+
+    #[test]
+    fn test_memo_matches_backtracking() {
+        for tiles in &[trap_board(5), low_deck(3, 2), "1R 1R 2R 2R 3R 3R 4R J J".parse().unwrap()] {
+            let expected: Vec<String> = Search::without_memo(*tiles, Tiles::new())
+                .map(|(sol, _)| sol.to_string())
+                .collect();
+            let actual: Vec<String> = solve(*tiles).iter().map(Solution::to_string).collect();
+            assert_eq!(actual, expected);
+        }
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn test_parallel_matches_solve() {
+        for tiles in &[trap_board(5), low_deck(3, 2), "1R 2R 3R 4R 5R 1R 2R 3R 4R 5R J".parse().unwrap()] {
+            assert_eq!(solve_parallel(*tiles), solve(*tiles));
+        }
+    }
+
+    fn unplaceable_helper(tiles: &str) -> String {
+        let mut tiles: Tiles = tiles.parse().unwrap();
+        let removed = unplaceable_tiles(tiles);
+        for tile in Tile::all() {
+            tiles.set_count(&tile, tiles.get_count(&tile) - removed.get_count(&tile));
+        }
+        assert!(is_solvable(tiles));
+        removed.to_string()
+    }
+
+    #[test]
+    fn test_unplaceable_solvable() {
+        assert_eq!(unplaceable_helper("1R 2R 3R 3B 3U 3O"), "");
+        assert_eq!(unplaceable_helper(""), "");
+    }
+
+    #[test]
+    fn test_unplaceable_stray_tiles() {
+        assert_eq!(unplaceable_helper("1R 2R 3R 7O 12B"), "7O 12B");
+        assert_eq!(unplaceable_helper("1R 2R"), "1R 2R");
+    }
+
+    #[test]
+    fn test_unplaceable_duplicate() {
+        assert_eq!(unplaceable_helper("1R 2R 3R 4R 4R"), "4R");
+        assert_eq!(unplaceable_helper("5B 5U 5O 5R 5R 5B"), "");
+        assert_eq!(unplaceable_helper("5B 5U 5O 5R 5R"), "5R");
+    }
+
+    #[test]
+    fn test_unplaceable_with_limits() {
+        let options = SolveOptions {
+            max_nodes: Some(5),
+            ..SolveOptions::default()
+        };
+        let board = low_deck(3, 0).difference(&"1R 1R".parse().unwrap());
+        assert_eq!(unplaceable_tiles_with_options(board, &options), Err(StopReason::NodeBudget));
+        let options = SolveOptions {
+            max_nodes: Some(100000),
+            ..SolveOptions::default()
+        };
+        assert_eq!(unplaceable_tiles_with_options(board, &options), Ok(unplaceable_tiles(board)));
+    }
+
+    #[test]
+    fn test_unplaceable_jokers() {
+        assert_eq!(unplaceable_helper("1R 1R 2R 3R J"), "1R");
+        assert_eq!(unplaceable_helper("J J"), "J J");
+        assert_eq!(unplaceable_helper("1R 2R 3R J J"), "");
+        assert_eq!(unplaceable_helper("1R 5R 9R J"), "1R 5R 9R J");
+        assert_eq!(unplaceable_helper("1R 2R 9R J J"), "9R");
+    }
+
+    /// Count the solutions without leftover jokers under the given
+    /// rules
+    fn rules_helper(tiles: &str, rules: RuleSet) -> usize {
+        let options = SolveOptions {
+            rules,
+            ..SolveOptions::default()
+        };
+        solve_with(tiles.parse().unwrap(), &options)
+            .solutions
+            .iter()
+            .filter(|solution| solution.leftover_jokers == 0)
+            .count()
+    }
+
+    #[test]
+    fn test_joker_before_run_to_13() {
+        assert_eq!(rules_helper("J 11R 12R 13R", RuleSet::standard()), 1);
+        assert_eq!(rules_helper("J J 12R 13R", RuleSet::standard()), 1);
+        assert!(is_solvable("J 5B 6B 7B 8B 9B 10B 11B 12B 13B".parse().unwrap()));
+    }
+
+    #[test]
+    fn test_jokers_between_naturals() {
+        assert_eq!(rules_helper("5R J J 8R", RuleSet::standard()), 1);
+        assert_eq!(rules_helper("5U 5O J J", RuleSet::standard()), 1);
+    }
+
+    #[test]
+    fn test_rule_wrap_around() {
+        let rules = RuleSet {
+            wrap_around: true,
+            ..RuleSet::standard()
+        };
+        assert_eq!(rules_helper("12R 13R 1R", RuleSet::standard()), 0);
+        assert_eq!(rules_helper("12R 13R 1R", rules), 1);
+        assert_eq!(rules_helper("13O J 2O 3O", rules), 1);
+        assert_eq!(rules_helper("1R 2R 3R", rules), 1);
+        let options = SolveOptions {
+            rules,
+            ..SolveOptions::default()
+        };
+        let solutions = solve_with("12R 13R 1R J".parse().unwrap(), &options).solutions;
+        let melds: Vec<String> = solutions
+            .iter()
+            .filter(|solution| solution.leftover_jokers == 0)
+            .map(|solution| solution.combos[0].to_string())
+            .collect();
+        assert_eq!(melds, vec!["12R 13R 1R J=2R"]);
+    }
+
+    #[test]
+    fn test_rule_min_naturals() {
+        let rules = RuleSet {
+            min_naturals: 1,
+            ..RuleSet::standard()
+        };
+        assert_eq!(rules_helper("5R J J", RuleSet::standard()), 0);
+        assert_eq!(rules_helper("5R J J", rules), 1);
+        let rules = RuleSet {
+            min_naturals: 3,
+            ..RuleSet::standard()
+        };
+        assert_eq!(rules_helper("1R J 3R", rules), 0);
+        assert_eq!(rules_helper("1R J 3R 4R", rules), 1);
+    }
+
+    #[test]
+    fn test_rule_adjacent_jokers() {
+        let rules = RuleSet {
+            adjacent_jokers: false,
+            ..RuleSet::standard()
+        };
+        assert_eq!(rules_helper("5R J J 8R", rules), 0);
+        assert_eq!(rules_helper("1R J 3R J 5R", rules), 1);
+        assert_eq!(rules_helper("5U 5O J J", rules), 1);
+    }
+
+    #[test]
+    fn test_rule_max_jokers() {
+        let rules = RuleSet {
+            max_jokers: Some(1),
+            ..RuleSet::standard()
+        };
+        assert_eq!(rules_helper("5R J J 8R", rules), 0);
+        assert_eq!(rules_helper("1R J 3R J 5R", rules), 0);
+        assert_eq!(rules_helper("1R J 3R", rules), 1);
+    }
+
+    #[test]
+    fn test_rule_duplicate_group_colors() {
+        let rules = RuleSet {
+            duplicate_group_colors: true,
+            ..RuleSet::standard()
+        };
+        assert_eq!(rules_helper("5R 5R 5B", RuleSet::standard()), 0);
+        assert_eq!(rules_helper("5R 5R 5B", rules), 1);
+        assert_eq!(rules_helper("5B 5B 5U 5U", rules), 1);
+        assert_eq!(rules_helper("1R 1B 1U", rules), 1);
+    }
+
+    #[test]
+    fn test_rule_sizes() {
+        let rules = RuleSet {
+            min_meld_size: 4,
+            max_group_size: 3,
+            max_run_length: 5,
+            ..RuleSet::standard()
+        };
+        assert_eq!(rules_helper("1R 2R 3R", rules), 0);
+        assert_eq!(rules_helper("1R 2R 3R 4R", rules), 1);
+        assert_eq!(rules_helper("1R 2R 3R 4R 5R 6R", rules), 0);
+        assert_eq!(rules_helper("1R 1B 1U 1O", rules), 0);
+    }
+
+    #[test]
+    fn test_special_jokers() {
+        let sols = solve("1R 2R J1".parse().unwrap());
+        assert_eq!(sols.len(), 1);
+        assert_eq!(sols[0].to_string(), "(1R 2R J1=3R), leftover jokers: 0");
+        assert_eq!(sols[0].combos[0].tiles(), "1R 2R J1".parse().unwrap());
+
+        // Either joker can be the one left over
+        let leftovers: Vec<Solution> = solve("1R 2R J J1".parse().unwrap())
+            .into_iter()
+            .filter(|sol| sol.leftover_jokers > 0)
+            .collect();
+        assert_eq!(leftovers.len(), 2);
+
+        let mut rules = RuleSet::standard();
+        rules.set("joker1=groups").unwrap();
+        assert_eq!(rules_helper("1R 2R J1", rules), 0);
+        assert_eq!(rules_helper("1R 1B J1", rules), 1);
+        assert_eq!(rules_helper("1R 2R J 1B 1U J1", rules), 1);
+        assert_eq!(unplaceable_tiles_with("1R 2R J1".parse().unwrap(), &rules).to_string(), "1R 2R J1");
+    }
+
+    #[test]
+    fn test_validate() {
+        let mut rules = RuleSet::standard();
+        rules.set("wrap-around=true").unwrap();
+        rules.set("joker1=runs").unwrap();
+        let options = SolveOptions { rules, ..SolveOptions::default() };
+        for tiles in &["1R 2R 3R 4R J J 5B 5U 5O 5O 11R 12R 13R", "12R 13R J1 1R 2B 2U J", "7R 8R 9R 7B 7U J J"] {
+            let tiles = tiles.parse().unwrap();
+            let solutions = solve_with(tiles, &options).solutions;
+            assert!(!solutions.is_empty());
+            for solution in solutions {
+                assert_eq!(solution.validate_with(&rules), Ok(()));
+                for meld in &solution.combos {
+                    assert!(Meld::classify_with(&meld.tiles(), &rules).contains(meld));
+                }
+            }
+        }
+
+        let solution = solve("1R 2R 3R 1R 2R 3R".parse().unwrap()).remove(0);
+        assert_eq!(solution.validate(), Ok(()));
+        let mut doubled = solution.clone();
+        doubled.combos.push(solution.combos[0].clone());
+        assert_eq!(
+            doubled.validate(),
+            Err(SolutionError::Tiles(TilesError::AlreadyHaveTwo(Tile::Number(1, Color::Red))))
+        );
+        let mut broken = solution.clone();
+        broken.combos[1].kind = MeldKind::Run { color: Color::Red, start: 1, len: 2 };
+        assert_eq!(
+            broken.validate(),
+            Err(SolutionError::InvalidMeld { index: 1, error: MeldError::TooShort })
+        );
+        assert_eq!(broken.validate().unwrap_err().to_string(), "Combo 2: Too few tiles");
+        broken.combos[1].kind = MeldKind::Run { color: Color::Red, start: 0, len: 3 };
+        assert_eq!(
+            broken.validate(),
+            Err(SolutionError::InvalidMeld { index: 1, error: MeldError::InvalidStart(0) })
+        );
+        broken.combos[1].kind = MeldKind::Run { color: Color::Red, start: 1, len: 0 };
+        assert_eq!(
+            broken.validate(),
+            Err(SolutionError::InvalidMeld { index: 1, error: MeldError::TooShort })
+        );
+    }
+
+    #[test]
+    fn test_special_joker_penalty() {
+        let mut rules = RuleSet::standard();
+        rules.set("joker2-penalty=50").unwrap();
+        rules.set("max-jokers=1").unwrap();
+        let board = "1R 2R".parse().unwrap();
+        let rack = "J J2".parse().unwrap();
+        let sols = solve_rack_with_rules(board, rack, Objective::PointSum, &rules);
+        assert!(!sols.is_empty());
+        for sol in sols {
+            assert_eq!(sol.played.to_string(), "J2");
+            assert_eq!(sol.score, 50);
+        }
+        assert_eq!(Objective::PointSum.score(&rack), 60);
+    }
+
+    #[test]
+    fn test_solve_with_no_limits() {
+        let tiles = low_deck(3, 2);
+        let res = solve_with(tiles, &SolveOptions::default());
+        assert!(res.is_exhaustive());
+        assert_eq!(res.solutions, solve(tiles));
+    }
+
+    #[test]
+    fn test_solve_with_max_solutions() {
+        let options = SolveOptions {
+            max_solutions: Some(5),
+            ..SolveOptions::default()
+        };
+        let res = solve_with(full_deck(), &options);
+        assert_eq!(res.stopped, Some(StopReason::MaxSolutions));
+        assert_eq!(res.solutions, solve_iter(full_deck()).take(5).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_solve_with_few_solutions() {
+        let options = SolveOptions {
+            max_solutions: Some(5),
+            ..SolveOptions::default()
+        };
+        let res = solve_with("1R 2R 3R".parse().unwrap(), &options);
+        assert!(res.is_exhaustive());
+        assert_eq!(res.solutions.len(), 1);
+    }
+
+    #[test]
+    fn test_solve_with_max_nodes() {
+        let options = SolveOptions {
+            max_nodes: Some(20),
+            ..SolveOptions::default()
+        };
+        let res = solve_with(trap_board(7), &options);
+        assert_eq!(res.stopped, Some(StopReason::NodeBudget));
+        assert!(res.solutions.is_empty());
+    }
+
+    #[test]
+    fn test_solve_with_deadline() {
+        let options = SolveOptions {
+            deadline: Some(Instant::now()),
+            ..SolveOptions::default()
+        };
+        let res = solve_with(full_deck(), &options);
+        assert_eq!(res.stopped, Some(StopReason::Deadline));
+    }
+
+    #[test]
+    fn test_solve_with_cancel() {
+        let cancel = Arc::new(AtomicBool::new(false));
+        let options = SolveOptions {
+            cancel: Some(cancel.clone()),
+            ..SolveOptions::default()
+        };
+        cancel.store(true, Ordering::Relaxed);
+        let res = solve_with(full_deck(), &options);
+        assert_eq!(res.stopped, Some(StopReason::Cancelled));
+        assert!(res.solutions.is_empty());
+    }
+
+    #[test]
+    fn test_solve_rack_with_options() {
+        let board = "1R 2R 3R".parse().unwrap();
+        let rack = "4R 5R 5B".parse().unwrap();
+        let res = solve_rack_with_options(board, rack, Objective::TileCount, &SolveOptions::default());
+        assert!(res.is_exhaustive());
+        let key = |sols: &[RackSolution]| sols.iter().map(|sol| sol.to_string()).collect::<Vec<_>>();
+        assert_eq!(key(&res.solutions), key(&solve_rack(board, rack)));
+
+        let options = SolveOptions {
+            max_nodes: Some(20),
+            ..SolveOptions::default()
+        };
+        let res = solve_rack_with_options(trap_board(7), Tiles::new(), Objective::TileCount, &options);
+        assert_eq!(res.stopped, Some(StopReason::NodeBudget));
+        assert!(res.solutions.is_empty());
+
+        let options = SolveOptions {
+            deadline: Some(Instant::now()),
+            ..SolveOptions::default()
+        };
+        let res = solve_rack_with_options(full_deck(), rack, Objective::TileCount, &options);
+        assert_eq!(res.stopped, Some(StopReason::Deadline));
+    }
+
+    /// A board where the joker has to go with 11R and 13R, but the
+    /// search first tries it as 3B and then has to work through every
+    /// arrangement of ranks 4 to `max_rank` before finding out.
+    fn trap_board(max_rank: u8) -> Tiles {
+        let mut tiles: Tiles = "1B 2B 1U 1O 1R 2U 2O 2R 11R 13R J".parse().unwrap();
+        for tile in Tile::all_no_jokers() {
+            match tile {
+                Tile::Number(rank, _) if rank >= 4 && rank <= max_rank => tiles.set_count(&tile, 2),
+                _ => (),
+            }
+        }
+        tiles
+    }
+
+    #[bench]
+    fn bench_full_deck_memo(b: &mut Bencher) {
+        b.iter(|| Search::new(full_deck(), Tiles::new()).take(10000).count())
+    }
+
+    #[bench]
+    fn bench_full_deck_backtracking(b: &mut Bencher) {
+        b.iter(|| Search::without_memo(full_deck(), Tiles::new()).take(10000).count())
+    }
+
+    #[bench]
+    fn bench_trap_memo(b: &mut Bencher) {
+        b.iter(|| Search::new(trap_board(7), Tiles::new()).next().is_some())
+    }
+
+    #[bench]
+    fn bench_trap_backtracking(b: &mut Bencher) {
+        b.iter(|| Search::without_memo(trap_board(7), Tiles::new()).next().is_some())
+    }
+
+    fn distinct_helper(tiles: &str) -> Vec<String> {
+        let tiles = tiles.parse().unwrap();
+        let distinct = solve_distinct(tiles);
+        for solution in solve(tiles) {
+            assert!(distinct.contains(&solution.canonical()));
+        }
+        distinct.iter().map(Solution::to_string).collect()
+    }
+
+    #[test]
+    fn test_distinct_run_order() {
+        assert_eq!(solve("1R 1R 2R 2R 3R 3R 4R".parse().unwrap()).len(), 2);
+        assert_eq!(
+            distinct_helper("1R 1R 2R 2R 3R 3R 4R"),
+            vec!["(1R 2R 3R), (1R 2R 3R 4R), leftover jokers: 0"]
+        );
+    }
+
+    #[test]
+    fn test_distinct_identical_runs() {
+        assert_eq!(distinct_helper("1R 2R 3R 1R 2R 3R").len(), 1);
+    }
+
+    #[test]
+    fn test_distinct_group_joker_color() {
+        // The joker could be either 1O or 1R, but only one is tried
+        assert_eq!(solve("1B 1U J".parse().unwrap()).len(), 1);
+        assert_eq!(distinct_helper("1B 1U J"), vec!["(1B 1U J=1O), leftover jokers: 0"]);
+    }
+
+    #[test]
+    fn test_distinct_duplicates_and_joker() {
+        assert_eq!(
+            distinct_helper("1R 1R 2R 2R 3R 3R J"),
+            vec![
+                "(1R 2R 3R), (1R 2R 3R), leftover jokers: 1",
+                "(1R 2R 3R), (1R 2R 3R J=4R), leftover jokers: 0",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_distinct_duplicate_groups() {
+        let distinct = distinct_helper("1B 1B 1U 1U 1O 1R J");
+        assert_eq!(solve("1B 1B 1U 1U 1O 1R J".parse().unwrap()).len(), 5);
+        assert_eq!(
+            distinct,
+            vec![
+                "(1B 1U 1O), (1B 1U 1R), leftover jokers: 1",
+                "(1B 1U 1O J=1R), (1B 1U 1R), leftover jokers: 0",
+                "(1B 1U 1O), (1B 1U J=1O 1R), leftover jokers: 0",
+                "(1B 1U J=1O), (1B 1U 1O 1R), leftover jokers: 0",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_distinct_larger_board() {
+        let tiles = low_deck(3, 2);
+        let distinct = solve_distinct(tiles);
+        assert!(distinct.len() < solve(tiles).len());
+        let unique: HashSet<&Solution> = distinct.iter().collect();
+        assert_eq!(unique.len(), distinct.len());
+    }
 }
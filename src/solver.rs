@@ -0,0 +1,262 @@
+use std::collections::HashSet;
+use super::tiles::Tiles;
+#[cfg(feature = "parallel")]
+use super::rules::RuleSet;
+use super::solve::*;
+use super::exact_cover::ExactCover;
+
+/// An algorithm for solving boards. Every backend finds the same
+/// arrangements, but they may find them in a different order, or
+/// find one arrangement more than once in different ways, so compare
+/// their solutions with `Solution::canonical`.
+pub trait Solver {
+    /// The name which picks this backend, see `solver`
+    fn name(&self) -> &'static str;
+
+    /// Pass each solution which places every tile from `board` to
+    /// `found`, until one of the limits in `options` is reached.
+    /// Returns why the search stopped early, if it did.
+    fn solve_each(&self, board: Tiles, options: &SolveOptions, found: &mut dyn FnMut(Solution)) -> Option<StopReason>;
+
+    /// The best plays from `rack` onto `board` found before one of the
+    /// limits in `options` is reached, see `solve_rack_with_options`.
+    fn solve_rack(&self, board: Tiles, rack: Tiles, objective: Objective, options: &SolveOptions) -> RackResult;
+
+    /// Collect the solutions from `solve_each`, like `solve_with`
+    fn solve(&self, board: Tiles, options: &SolveOptions) -> SolveResult {
+        let mut solutions = vec![];
+        let stopped = self.solve_each(board, options, &mut |solution| solutions.push(solution));
+        SolveResult { solutions, stopped }
+    }
+
+    /// The distinct solutions in canonical form, like `solve_distinct`.
+    /// `options.max_solutions` limits the solutions found before the
+    /// repeats are left out.
+    fn solve_distinct(&self, board: Tiles, options: &SolveOptions) -> SolveResult {
+        let mut seen = HashSet::new();
+        let mut solutions = vec![];
+        let stopped = self.solve_each(board, options, &mut |solution| {
+            let solution = solution.canonical();
+            if seen.insert(solution.clone()) {
+                solutions.push(solution);
+            }
+        });
+        SolveResult { solutions, stopped }
+    }
+
+    /// A single best play from the rack, if the board can be solved
+    /// at all
+    fn best_play(&self, board: Tiles, rack: Tiles, objective: Objective, options: &SolveOptions) -> Option<RackSolution> {
+        self.solve_rack(board, rack, objective, options).solutions.into_iter().next()
+    }
+}
+
+/// The depth-first search from `solve`, which remembers dead ends.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Backtracking;
+
+impl Solver for Backtracking {
+    fn name(&self) -> &'static str {
+        "backtracking"
+    }
+
+    fn solve_each(&self, board: Tiles, options: &SolveOptions, found: &mut dyn FnMut(Solution)) -> Option<StopReason> {
+        let mut solutions = solve_iter_with(board, options);
+        for solution in solutions.by_ref() {
+            found(solution);
+        }
+        solutions.stopped()
+    }
+
+    fn solve_rack(&self, board: Tiles, rack: Tiles, objective: Objective, options: &SolveOptions) -> RackResult {
+        solve_rack_with_options(board, rack, objective, options)
+    }
+}
+
+/// The search from `solve_parallel`. It doesn't support limits, house
+/// rules or racks, and falls back to `Backtracking` for those.
+#[cfg(feature = "parallel")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Parallel;
+
+#[cfg(feature = "parallel")]
+impl Solver for Parallel {
+    fn name(&self) -> &'static str {
+        "parallel"
+    }
+
+    fn solve_each(&self, board: Tiles, options: &SolveOptions, found: &mut dyn FnMut(Solution)) -> Option<StopReason> {
+        if options.max_solutions.is_some()
+            || options.max_nodes.is_some()
+            || options.deadline.is_some()
+            || options.cancel.is_some()
+            || options.rules != RuleSet::standard()
+        {
+            return Backtracking.solve_each(board, options, found);
+        }
+        for solution in solve_parallel(board) {
+            found(solution);
+        }
+        None
+    }
+
+    fn solve_rack(&self, board: Tiles, rack: Tiles, objective: Objective, options: &SolveOptions) -> RackResult {
+        Backtracking.solve_rack(board, rack, objective, options)
+    }
+}
+
+/// Every backend which is built in, starting with the default
+pub fn solvers() -> Vec<Box<dyn Solver>> {
+    vec![
+        #[cfg(feature = "parallel")]
+        Box::new(Parallel),
+        Box::new(Backtracking),
+        Box::new(ExactCover),
+    ]
+}
+
+/// Find a backend by name, as given on the command line:
+/// `backtracking` for the search from `solve`, `exact-cover` for
+/// `ExactCover`, or `parallel` with the `parallel` feature.
+pub fn solver(name: &str) -> Option<Box<dyn Solver>> {
+    solvers().into_iter().find(|solver| solver.name() == name)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use super::super::tiles::Tile;
+    use super::super::rules::RuleSet;
+    use super::super::solve::test::low_deck;
+    use std::time::Instant;
+
+    /// Boards which need the two copies of a tile, jokers, leftover
+    /// jokers, or have no solutions at all
+    fn corpus() -> Vec<Tiles> {
+        let mut boards: Vec<Tiles> = [
+            "",
+            "1R",
+            "J",
+            "1R 2R 3R",
+            "1R 2R 3R 4R 5R 6R 1B 1U 1O J J 7R 8R",
+            "1R 1R 2R 2R 3R 3R 1B 1U 1O 1O J",
+            "1R 1R 2R 2R 3R 3R 4R J J",
+            "12R 13R J 12B 12U 12O",
+            "5B 5U 5O 5R 5B 5U 5O 5R J",
+            "1B 2B 1U 1O 1R 2U 2O 2R 11R 13R J",
+            "1R 1B 1U 1O J",
+        ]
+        .iter()
+        .map(|s| s.parse().unwrap())
+        .collect();
+        boards.push(low_deck(3, 2));
+        // Runs which end at 13, or wrap around with house rules
+        let mut high: Tiles = "1R 2R 1B J".parse().unwrap();
+        for tile in Tile::all_no_jokers() {
+            if let Tile::Number(11..=13, _) = tile {
+                high.set_count(&tile, 2);
+            }
+        }
+        boards.push(high);
+        boards
+    }
+
+    fn canonical(solutions: &[Solution]) -> HashSet<Solution> {
+        solutions.iter().map(Solution::canonical).collect()
+    }
+
+    #[test]
+    fn test_lookup() {
+        assert_eq!(solver("backtracking").unwrap().name(), "backtracking");
+        assert!(solver("ilp").is_none());
+        for backend in solvers() {
+            assert_eq!(solver(backend.name()).unwrap().name(), backend.name());
+        }
+    }
+
+    #[test]
+    fn test_backends_agree() {
+        let mut rules = RuleSet::standard();
+        rules.set("wrap-around=true").unwrap();
+        let wrap = SolveOptions { rules, ..SolveOptions::default() };
+        for options in &[SolveOptions::default(), wrap] {
+            for board in corpus() {
+                let expected = Backtracking.solve(board, options);
+                assert!(expected.is_exhaustive());
+                let expected = canonical(&expected.solutions);
+                for backend in solvers() {
+                    let result = backend.solve(board, options);
+                    assert!(result.is_exhaustive(), "{} {}", backend.name(), board);
+                    for solution in &result.solutions {
+                        assert_eq!(solution.validate_with(&options.rules), Ok(()), "{} {}", backend.name(), board);
+                    }
+                    assert_eq!(canonical(&result.solutions), expected, "{} {}", backend.name(), board);
+                    let distinct = backend.solve_distinct(board, options);
+                    assert!(distinct.is_exhaustive());
+                    assert_eq!(canonical(&distinct.solutions), expected, "{} {}", backend.name(), board);
+                    if options.rules == RuleSet::standard() {
+                        assert_eq!(distinct.solutions.len(), solve_distinct(board).len());
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_backends_agree_on_racks() {
+        let racks: Vec<(&str, &str)> = vec![
+            ("", "1R 2R 3R 7B"),
+            ("1R 2R 3R", "4R 4B 4U 1R"),
+            ("5B 5U 5O", "5R J 6R 7R 13O"),
+            ("J 8R 9R", "10R 10B 10U 10O 7R"),
+            ("1R 2R", "12B 13B"),
+        ];
+        let objectives = [Objective::TileCount, Objective::PointSum, Objective::HighTiles];
+        let options = SolveOptions::default();
+        for (board, rack) in racks {
+            let board: Tiles = board.parse().unwrap();
+            let rack: Tiles = rack.parse().unwrap();
+            for objective in &objectives {
+                let key = |solutions: Vec<RackSolution>| -> HashSet<(Solution, Tiles, u64)> {
+                    solutions
+                        .into_iter()
+                        .map(|solution| (solution.solution.canonical(), solution.played, solution.score))
+                        .collect()
+                };
+                let expected = key(Backtracking.solve_rack(board, rack, *objective, &options).solutions);
+                for backend in solvers() {
+                    let actual = key(backend.solve_rack(board, rack, *objective, &options).solutions);
+                    assert_eq!(actual, expected, "{} {} {}", backend.name(), board, rack);
+                    let best = backend.best_play(board, rack, *objective, &options);
+                    assert_eq!(best.is_some(), !expected.is_empty());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_limits() {
+        let options = SolveOptions { max_solutions: Some(3), ..SolveOptions::default() };
+        let board = low_deck(4, 1);
+        for backend in solvers() {
+            let result = backend.solve(board, &options);
+            assert_eq!(result.solutions.len(), 3, "{}", backend.name());
+            assert_eq!(result.stopped, Some(StopReason::MaxSolutions));
+        }
+        for backend in solvers() {
+            let result = backend.solve_distinct(board, &options);
+            assert!(!result.solutions.is_empty() && result.solutions.len() <= 3, "{}", backend.name());
+            assert_eq!(result.stopped, Some(StopReason::MaxSolutions));
+        }
+        let unsolvable: Tiles = "1R".parse().unwrap();
+        for backend in solvers() {
+            assert!(backend.solve(unsolvable, &options).solutions.is_empty());
+        }
+        let options = SolveOptions { deadline: Some(Instant::now()), ..SolveOptions::default() };
+        let rack = "1R 2R 3R".parse().unwrap();
+        for backend in solvers() {
+            let result = backend.solve_rack(board, rack, Objective::TileCount, &options);
+            assert_eq!(result.stopped, Some(StopReason::Deadline), "{}", backend.name());
+        }
+    }
+}
@@ -0,0 +1,208 @@
+use std::collections::BTreeSet;
+use lazy_static::lazy_static;
+use super::tiles::*;
+use super::meld::*;
+use super::rules::RuleSet;
+
+/// A meld in a `MeldTable`, with the physical tiles it is made of
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub meld: Meld,
+    /// The tiles to play this meld, with plain jokers as `Tile::Joker`.
+    /// Since this is a `Tiles`, checking whether a meld can be made
+    /// from some other tiles is a couple of bit operations.
+    pub tiles: Tiles,
+}
+
+/// Every legal meld which can be made from the standard set of tiles
+/// and its two plain jokers, including each way of placing the
+/// jokers. Solvers and hint tools can share it rather than building
+/// runs and groups for every search.
+///
+/// The table isn't generated at build time: each `Meld` in it owns a
+/// `Vec` of colors, so it can't be a `static` as it is, and building
+/// the standard table at run time takes about 20 milliseconds in a
+/// release build. `MeldTable::standard` builds it once, on first use.
+#[derive(Debug, Clone)]
+pub struct MeldTable {
+    entries: Vec<Entry>,
+    /// The indices of the entries using each tile, by `Tile::index`
+    by_tile: Vec<Vec<usize>>,
+}
+
+impl MeldTable {
+    /// The table for the standard rules. It is built the first time
+    /// it's needed, and then kept for the rest of the program, rather
+    /// than generated ahead of time.
+    pub fn standard() -> &'static MeldTable {
+        lazy_static! {
+            static ref STANDARD: MeldTable = MeldTable::new(&RuleSet::standard());
+        }
+        &STANDARD
+    }
+
+    /// The table of melds which follow `rules`. Special jokers aren't
+    /// included, as they would multiply the size of the table.
+    pub fn new(rules: &RuleSet) -> MeldTable {
+        let mut kinds = vec![];
+        for color in Color::all() {
+            for start in 1..=13 {
+                for len in 1..=13 {
+                    kinds.push(MeldKind::Run { color, start, len });
+                }
+            }
+        }
+        // Up to two of each color, in color order
+        let mut color_choices: Vec<Vec<Color>> = vec![vec![]];
+        for color in Color::all() {
+            color_choices = color_choices
+                .into_iter()
+                .flat_map(|colors| {
+                    (0..=2)
+                        .map(|count| {
+                            let mut colors = colors.clone();
+                            colors.extend((0..count).map(|_| color));
+                            colors
+                        })
+                        .collect::<Vec<_>>()
+                })
+                .collect();
+        }
+        for rank in 1..=13 {
+            for colors in &color_choices {
+                kinds.push(MeldKind::Group { rank, colors: colors.clone() });
+            }
+        }
+
+        // Sorted and without the duplicates from jokers standing in
+        // for either copy of a tile
+        let mut melds = BTreeSet::new();
+        for kind in kinds {
+            let positions = kind.positions();
+            // No jokers, one or two, as there are two in the set
+            let mut placements = vec![vec![]];
+            for (i, first) in positions.iter().enumerate() {
+                placements.push(vec![*first]);
+                for second in &positions[i + 1..] {
+                    placements.push(vec![*first, *second]);
+                }
+            }
+            for jokers in placements {
+                let jokers: Tiles = jokers.into_iter().collect();
+                let meld = Meld { kind: kind.clone(), jokers, specials: Tiles::new() };
+                if meld.check(rules).is_ok() {
+                    melds.insert(meld);
+                }
+            }
+        }
+
+        let entries: Vec<Entry> = melds
+            .into_iter()
+            .map(|meld| Entry { tiles: meld.tiles(), meld })
+            .collect();
+        let mut by_tile = vec![vec![]; 64];
+        for (i, entry) in entries.iter().enumerate() {
+            for (tile, _) in entry.tiles.iter() {
+                by_tile[usize::from(tile.index() / 2)].push(i);
+            }
+        }
+        MeldTable { entries, by_tile }
+    }
+
+    /// Every meld in the table, in `Meld` order
+    pub fn entries(&self) -> &[Entry] {
+        &self.entries
+    }
+
+    /// The melds which use at least one of `tile`. For `Tile::Joker`
+    /// that's every meld with a joker in it.
+    pub fn containing(&self, tile: &Tile) -> impl Iterator<Item = &Entry> + '_ {
+        let indices: &[usize] = if tile.is_standard() {
+            &self.by_tile[usize::from(tile.index() / 2)]
+        } else {
+            &[]
+        };
+        indices.iter().map(move |i| &self.entries[*i])
+    }
+
+    /// The melds which can be made from `tiles`
+    pub fn within(&self, tiles: Tiles) -> impl Iterator<Item = &Entry> + '_ {
+        self.entries.iter().filter(move |entry| entry.tiles.is_subset(&tiles))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::collections::HashMap;
+    use std::ptr;
+
+    #[test]
+    fn test_standard_table() {
+        let table = MeldTable::standard();
+        assert!(ptr::eq(table, MeldTable::standard()));
+
+        // A run of length `len` can have up to two jokers, as long as
+        // two natural tiles are left
+        let runs: usize = (3..=13)
+            .map(|len| {
+                let placements = 1 + len + if len > 3 { len * (len - 1) / 2 } else { 0 };
+                (14 - len) * placements
+            })
+            .sum();
+        // Groups are the 4 choices of 3 colors, with up to one joker,
+        // or all 4 colors, with up to two
+        let groups = 13 * (4 * (1 + 3) + (1 + 4 + 6));
+        assert_eq!(table.entries().len(), 4 * runs + groups);
+
+        let rules = RuleSet::standard();
+        let mut by_tiles: HashMap<Tiles, Vec<Meld>> = HashMap::new();
+        for entry in table.entries() {
+            assert_eq!(entry.meld.check(&rules), Ok(()));
+            assert_eq!(entry.tiles, entry.meld.tiles());
+            by_tiles.entry(entry.tiles).or_default().push(entry.meld.clone());
+        }
+        for (tiles, mut melds) in by_tiles {
+            let mut classified = Meld::classify(&tiles);
+            melds.sort();
+            classified.sort();
+            assert_eq!(melds, classified);
+        }
+    }
+
+    #[test]
+    fn test_rules_table() {
+        let mut rules = RuleSet::standard();
+        rules.set("wrap-around=true").unwrap();
+        let table = MeldTable::new(&rules);
+        let wrapped: Tiles = "12R 13R 1R".parse().unwrap();
+        assert_eq!(table.within(wrapped).count(), 1);
+        assert_eq!(MeldTable::standard().within(wrapped).count(), 0);
+    }
+
+    #[test]
+    fn test_lookup() {
+        let table = MeldTable::standard();
+        let tile = Tile::Number(7, Color::Red);
+        let containing: Vec<&Entry> = table.containing(&tile).collect();
+        assert!(!containing.is_empty());
+        assert_eq!(
+            containing,
+            table.entries().iter().filter(|entry| entry.tiles.get_count(&tile) > 0).collect::<Vec<_>>()
+        );
+        assert_eq!(table.containing(&Tile::Special(1)).count(), 0);
+        assert_eq!(
+            table.containing(&Tile::Joker).count(),
+            table.entries().iter().filter(|entry| entry.tiles.get_joker_count() > 0).count()
+        );
+
+        let tiles: Tiles = "5R 6R 7R 7B 7O J".parse().unwrap();
+        let within: Vec<String> = table.within(tiles).map(|entry| entry.meld.to_string()).collect();
+        assert_eq!(within.len(), 16);
+        assert!(within.contains(&"5R 6R 7R".to_string()));
+        assert!(within.contains(&"7B 7O 7R".to_string()));
+        for entry in table.within(tiles) {
+            assert!(entry.tiles.is_subset(&tiles));
+        }
+    }
+}
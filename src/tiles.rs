@@ -1,13 +1,18 @@
 use std::fmt::Display;
 use std::str::FromStr;
 
-/// The four colors
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+/// The four colors, along with the extra colors from editions for
+/// more players. Only the first four are part of the standard set.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
 pub enum Color {
     Black,
     Blue,
     Orange,
     Red,
+    Green,
+    Purple,
 }
 
 impl Display for Color {
@@ -20,6 +25,8 @@ impl Display for Color {
                 Color::Blue => 'U',
                 Color::Orange => 'O',
                 Color::Red => 'R',
+                Color::Green => 'G',
+                Color::Purple => 'P',
             }
         )
     }
@@ -35,17 +42,22 @@ impl Color {
             Color::Black => Some(Color::Blue),
             Color::Blue => Some(Color::Orange),
             Color::Orange => Some(Color::Red),
-            Color::Red => None,
+            Color::Red | Color::Green | Color::Purple => None,
         }
     }
 
-    /// Iterate all of the colors
+    /// Iterate all of the colors in the standard set
     pub fn all() -> AllColors {
         AllColors { next: Some(Color::min_value()) }
     }
+
+    /// Every color, including those which aren't in the standard set
+    pub fn extended() -> [Color; 6] {
+        [Color::Black, Color::Blue, Color::Orange, Color::Red, Color::Green, Color::Purple]
+    }
 }
 
-/// Iterate all of the colors
+/// Iterate all of the colors in the standard set
 pub struct AllColors {
     next: Option<Color>,
 }
@@ -60,11 +72,19 @@ impl Iterator for AllColors {
     }
 }
 
+/// The number of kinds of special joker, see `Tile::Special`.
+pub const SPECIAL_JOKERS: u8 = 3;
+
 /// Represent a single tile
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Tile {
     Joker,
     Number(u8, Color),
+    /// One of the special jokers from some editions and house rules,
+    /// numbered from 1 to `SPECIAL_JOKERS` and written `J1`, `J2`
+    /// and so on. What each one can stand in for, and its penalty,
+    /// is up to the `RuleSet`.
+    Special(u8),
 }
 
 impl Tile {
@@ -76,7 +96,9 @@ impl Tile {
     /// Get the next valued tile
     pub fn next(&self) -> Option<Tile> {
         match self {
-            Tile::Joker => None,
+            Tile::Joker => Some(Tile::Special(1)),
+            Tile::Special(n) if *n < SPECIAL_JOKERS => Some(Tile::Special(n + 1)),
+            Tile::Special(_) => None,
             Tile::Number(rank, color) => Some(match color.next() {
                 Some(color) => Tile::Number(*rank, color),
                 None => {
@@ -104,65 +126,138 @@ impl Tile {
         }
     }
 
+    /// Iterate over every kind of joker, starting with the plain one
+    pub fn jokers() -> AllTiles {
+        AllTiles {
+            next: Some(Tile::Joker),
+        }
+    }
+
+    /// Is this any kind of joker?
+    pub fn is_joker(&self) -> bool {
+        match self {
+            Tile::Joker | Tile::Special(_) => true,
+            Tile::Number(..) => false,
+        }
+    }
+
+    /// The penalty for holding this tile at the end of a game: the
+    /// rank for numbered tiles, and 30 for jokers. Special jokers
+    /// can be worth more, see `RuleSet::penalty`.
+    pub fn penalty(&self) -> u8 {
+        match self {
+            Tile::Joker | Tile::Special(_) => 30,
+            Tile::Number(rank, _) => *rank,
+        }
+    }
+
+    /// Is this tile one which `Tiles` can hold? That is the standard
+    /// set, along with the special jokers.
+    pub fn is_standard(&self) -> bool {
+        match self {
+            Tile::Joker => true,
+            Tile::Special(n) => *n >= 1 && *n <= SPECIAL_JOKERS,
+            Tile::Number(rank, color) => *rank >= 1 && *rank <= 13 && *color <= Color::Red,
+        }
+    }
+
     /// Internal: an index in the Tiles structure for this
-    /// Tile. Should be 2 greater than the previous one.
-    fn index(&self) -> u8 {
+    /// Tile. Should be 2 greater than the previous one. Panics for
+    /// tiles outside the standard set, which have no index.
+    pub(crate) fn index(&self) -> u8 {
+        if !self.is_standard() {
+            panic!("Not a standard tile: {}", self);
+        }
         match self {
             Tile::Joker => 104,
+            Tile::Special(n) => 104 + n * 2,
             Tile::Number(rank, color) => {
                 let color_index = match color {
                     Color::Black => 0,
                     Color::Blue => 1,
                     Color::Orange => 2,
                     Color::Red => 3,
+                    Color::Green | Color::Purple => unreachable!(),
                 };
                 ((rank - 1) * 4 + color_index) * 2
             }
         }
     }
+
+    /// Internal: the tile with the given index, the inverse of
+    /// `index`
+    fn from_index(index: u8) -> Tile {
+        match index / 2 {
+            52 => Tile::Joker,
+            n if n > 52 => Tile::Special(n - 52),
+            n => {
+                let color = match n % 4 {
+                    0 => Color::Black,
+                    1 => Color::Blue,
+                    2 => Color::Orange,
+                    _ => Color::Red,
+                };
+                Tile::Number(n / 4 + 1, color)
+            }
+        }
+    }
 }
 
 impl FromStr for Tile {
     type Err = TilesError;
 
     fn from_str(s: &str) -> Result<Tile, Self::Err> {
-        let get_res = || {
-            if s.is_empty() {
-                return None;
-            }
-            if s == "J" || s == "j" {
-                return Some(Tile::Joker);
-            }
+        match parse_tile(s, &Color::extended()[..4], 13) {
+            None => Err(TilesError::InvalidTileString(String::from(s))),
+            Some(tile) => Ok(tile),
+        }
+    }
+}
 
-            let bytes = s.as_bytes();
-            let color = match bytes[bytes.len() - 1] {
-                b'B' | b'b' => Color::Black,
-                b'U' | b'u' => Color::Blue,
-                b'O' | b'o' => Color::Orange,
-                b'R' | b'r' => Color::Red,
-                _ => return None,
-            };
-
-            let mut rank = 0;
-            for b in bytes[..bytes.len() - 1].iter() {
-                if *b < b'0' || *b > b'9' {
-                    return None;
-                }
-                rank *= 10;
-                rank += *b - b'0';
-            }
+/// Parse a single tile, allowing only the given colors and ranks up
+/// to `max_rank`.
+pub(crate) fn parse_tile(s: &str, colors: &[Color], max_rank: u8) -> Option<Tile> {
+    if s.is_empty() {
+        return None;
+    }
+    if s == "J" || s == "j" {
+        return Some(Tile::Joker);
+    }
 
-            if rank < 1 || rank > 13 {
-                None
-            } else {
-                Some(Tile::Number(rank, color))
-            }
+    let bytes = s.as_bytes();
+    if bytes[0] == b'J' || bytes[0] == b'j' {
+        return match &s[1..] {
+            "1" => Some(Tile::Special(1)),
+            "2" => Some(Tile::Special(2)),
+            "3" => Some(Tile::Special(3)),
+            _ => None,
         };
+    }
+    let color = match bytes[bytes.len() - 1] {
+        b'B' | b'b' => Color::Black,
+        b'U' | b'u' => Color::Blue,
+        b'O' | b'o' => Color::Orange,
+        b'R' | b'r' => Color::Red,
+        b'G' | b'g' => Color::Green,
+        b'P' | b'p' => Color::Purple,
+        _ => return None,
+    };
+    if !colors.contains(&color) {
+        return None;
+    }
 
-        match get_res() {
-            None => Err(TilesError::InvalidTileString(String::from(s))),
-            Some(tile) => Ok(tile),
+    let mut rank: u8 = 0;
+    for b in bytes[..bytes.len() - 1].iter() {
+        if *b < b'0' || *b > b'9' {
+            return None;
         }
+        rank = rank.checked_mul(10)?.checked_add(*b - b'0')?;
+    }
+
+    if rank >= 1 && rank <= max_rank {
+        Some(Tile::Number(rank, color))
+    } else {
+        None
     }
 }
 
@@ -171,6 +266,7 @@ impl Display for Tile {
         match self {
             Tile::Joker => write!(fmt, "J"),
             Tile::Number(rank, color) => write!(fmt, "{}{}", rank, color),
+            Tile::Special(n) => write!(fmt, "J{}", n),
         }
     }
 }
@@ -210,19 +306,28 @@ impl Iterator for AllTilesNoJokers {
 }
 
 /// Represent the tiles available
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct Tiles {
     tiles: u128,
 }
 
+/// The low bit of the count of every tile. Counts are stored in two
+/// bits each, as 0, 1 or 2 in binary, which lets whole sets of tiles
+/// be combined at once.
+const LOW_BITS: u128 = 0x5555_5555_5555_5555_5555_5555_5555_5555;
+
 impl Tiles {
     /// An empty set of tiles
     pub fn new() -> Tiles {
         Tiles { tiles: 0 }
     }
 
-    /// Add another tile of the given type. May fail if we already have 2.
+    /// Add another tile of the given type. May fail if we already
+    /// have 2, or the tile isn't part of the standard set.
     pub fn add_tile(&mut self, tile: &Tile) -> Result<(), TilesError> {
+        if !tile.is_standard() {
+            return Err(TilesError::NotInTileSet(*tile));
+        }
         let count = self.get_count(tile);
         if count >= 2 {
             Err(TilesError::AlreadyHaveTwo(*tile))
@@ -232,8 +337,12 @@ impl Tiles {
         }
     }
 
-    /// How many of this kind of tile do we have?
+    /// How many of this kind of tile do we have? Always none for
+    /// tiles outside the standard set.
     pub fn get_count(&self, tile: &Tile) -> u8 {
+        if !tile.is_standard() {
+            return 0;
+        }
         let index_x = tile.index();
         let index_y = index_x + 1;
 
@@ -253,8 +362,15 @@ impl Tiles {
         }
     }
 
-    /// Set the number of tiles of this kind. This will panic if you give it a count greater than 2.
+    /// Set the number of tiles of this kind. This will panic if you give it a count greater than 2,
+    /// or any copies of a tile outside the standard set.
     pub fn set_count(&mut self, tile: &Tile, count: u8) {
+        if !tile.is_standard() {
+            if count > 0 {
+                panic!("Not a standard tile: {}", tile);
+            }
+            return;
+        }
         let (y, x) = match count {
             0 => (false, false),
             1 => (false, true),
@@ -280,35 +396,246 @@ impl Tiles {
 
     /// How many tiles total do we have?
     pub fn get_total_count(&self) -> u8 {
-        Tile::all()
-            .map(|tile| self.get_count(&tile))
-            .fold(0, |x, y| x + y)
+        (self.low().count_ones() + 2 * self.high().count_ones()) as u8
+    }
+
+    /// Do we have no tiles at all?
+    pub fn is_empty(&self) -> bool {
+        self.tiles == 0
+    }
+
+    /// The low bit of each count
+    fn low(&self) -> u128 {
+        self.tiles & LOW_BITS
+    }
+
+    /// The high bit of each count, moved down to where the low bit is
+    fn high(&self) -> u128 {
+        (self.tiles >> 1) & LOW_BITS
+    }
+
+    /// Which tiles we have at least one of, at their low bits
+    fn present(&self) -> u128 {
+        self.low() | self.high()
+    }
+
+    fn from_bits(low: u128, high: u128) -> Tiles {
+        Tiles { tiles: low | high << 1 }
+    }
+
+    /// All of the tiles in both, or `None` if that would be more than
+    /// two of a tile.
+    pub fn checked_add(&self, other: &Tiles) -> Option<Tiles> {
+        // Adding overflows where both have a tile and one of them
+        // has two
+        if self.present() & other.present() & (self.high() | other.high()) != 0 {
+            return None;
+        }
+        // Otherwise no count carries over into the next one
+        Some(Tiles { tiles: self.tiles + other.tiles })
+    }
+
+    /// The tiles left after taking away `other`, or `None` if we
+    /// don't have all of them.
+    pub fn checked_sub(&self, other: &Tiles) -> Option<Tiles> {
+        if other.is_subset(self) {
+            Some(Tiles { tiles: self.tiles - other.tiles })
+        } else {
+            None
+        }
+    }
+
+    /// The tiles left after taking away any which are in `other`
+    pub fn difference(&self, other: &Tiles) -> Tiles {
+        Tiles { tiles: self.tiles - self.intersection(other).tiles }
+    }
+
+    /// The most of each tile in either
+    pub fn union(&self, other: &Tiles) -> Tiles {
+        let high = self.high() | other.high();
+        Tiles::from_bits((self.low() | other.low()) & !high, high)
+    }
+
+    /// The fewest of each tile in either
+    pub fn intersection(&self, other: &Tiles) -> Tiles {
+        let high = self.high() & other.high();
+        Tiles::from_bits(self.present() & other.present() & !high, high)
+    }
+
+    /// Do we have no more of any tile than `other`?
+    pub fn is_subset(&self, other: &Tiles) -> bool {
+        // Having two of a tile which `other` has fewer of, or one of
+        // a tile which `other` has none of
+        let more = (self.high() & !other.high()) | (self.low() & !other.present());
+        more == 0
+    }
+
+    /// Iterate over the tiles we have along with how many of each,
+    /// in the same order as `Tile::all`.
+    pub fn iter(&self) -> TileCounts {
+        TileCounts { tiles: *self }
+    }
+
+    /// Do we have any special jokers? This is checked often by the
+    /// solver, so is cheaper than counting them.
+    pub fn has_special_jokers(&self) -> bool {
+        self.tiles >> Tile::Special(1).index() != 0
+    }
+
+    /// How many jokers do we have, of every kind?
+    pub fn get_joker_count(&self) -> u8 {
+        Tiles { tiles: self.tiles >> Tile::Joker.index() }.get_total_count()
+    }
+}
+
+/// Iterator over the tiles in a `Tiles` and how many of each there
+/// are, see `Tiles::iter`.
+pub struct TileCounts {
+    tiles: Tiles,
+}
+
+impl Iterator for TileCounts {
+    type Item = (Tile, u8);
+
+    fn next(&mut self) -> Option<(Tile, u8)> {
+        let present = self.tiles.present();
+        if present == 0 {
+            return None;
+        }
+        let tile = Tile::from_index(present.trailing_zeros() as u8);
+        let count = self.tiles.get_count(&tile);
+        self.tiles.set_count(&tile, 0);
+        Some((tile, count))
+    }
+}
+
+impl std::iter::FromIterator<Tile> for Tiles {
+    /// Collect tiles, panicking if there are more than two of any of
+    /// them, like `set_count`.
+    fn from_iter<I: IntoIterator<Item = Tile>>(iter: I) -> Tiles {
+        let mut tiles = Tiles::new();
+        tiles.extend(iter);
+        tiles
+    }
+}
+
+impl Extend<Tile> for Tiles {
+    fn extend<I: IntoIterator<Item = Tile>>(&mut self, iter: I) {
+        for tile in iter {
+            if let Err(e) = self.add_tile(&tile) {
+                panic!("Cannot add tile: {}", e);
+            }
+        }
+    }
+}
+
+impl Default for Tiles {
+    fn default() -> Tiles {
+        Tiles::new()
     }
 }
 
 impl FromStr for Tiles {
     type Err = TilesError;
 
+    /// Parse a list of tiles separated by whitespace or commas.
+    /// Besides single tiles, the list can have shorthand for several
+    /// tiles: a run like `3-7R`, a group like `5BUO`, or a number of
+    /// copies like `2x4O`. Errors say which tile was wrong with
+    /// `TilesError::At`.
     fn from_str(s: &str) -> Result<Tiles, Self::Err> {
-        let mut tiles = Tiles::new();
+        parse_list(s, &|token| token.parse().ok())
+    }
+}
+
+/// Parse a list of tiles like `Tiles::from_str` does, with each tile
+/// read by `parse_tile`.
+pub(crate) fn parse_list(s: &str, parse_tile: &dyn Fn(&str) -> Option<Tile>) -> Result<Tiles, TilesError> {
+    Ok(parse_layout(s, parse_tile)?.into_iter().collect())
+}
+
+/// Parse a list of tiles like `parse_list`, keeping them in the order
+/// they are written.
+pub(crate) fn parse_layout(s: &str, parse_tile: &dyn Fn(&str) -> Option<Tile>) -> Result<Vec<Tile>, TilesError> {
+    let mut tiles = Tiles::new();
+    let mut layout = vec![];
+
+    for (offset, token) in tokens(s, |c| c.is_whitespace() || c == ',') {
+        let index = layout.len();
+        let at = |index, error| TilesError::At { offset, index, error: Box::new(error) };
+        let expanded = match expand(token, parse_tile) {
+            None => return Err(at(index, TilesError::InvalidTileString(String::from(token)))),
+            Some(expanded) => expanded,
+        };
+        for (i, tile) in expanded.iter().enumerate() {
+            tiles.add_tile(tile).map_err(|e| at(index + i, e))?;
+            layout.push(*tile);
+        }
+    }
+
+    Ok(layout)
+}
 
-        for s in s.split(' ') {
-            let s = s.trim();
-            if !s.is_empty() {
-                let tile = s.parse()?;
-                tiles.add_tile(&tile)?;
+/// The tiles written as `token`, which is either a single tile or
+/// shorthand for several of them
+fn expand(token: &str, parse_tile: &dyn Fn(&str) -> Option<Tile>) -> Option<Vec<Tile>> {
+    if let Some(tile) = parse_tile(token) {
+        return Some(vec![tile]);
+    }
+
+    // Copies, like 2x4O
+    if let Some(x) = token.find(&['x', 'X'][..]) {
+        let copies: usize = token[..x].parse().ok()?;
+        if copies == 0 {
+            return None;
+        }
+        let tiles = expand(&token[x + 1..], parse_tile)?;
+        return Some(tiles.iter().cycle().take(copies * tiles.len()).cloned().collect());
+    }
+
+    // A run, like 3-7R
+    if let Some(dash) = token.find('-') {
+        let first: u8 = token[..dash].parse().ok()?;
+        return match parse_tile(&token[dash + 1..]) {
+            Some(Tile::Number(last, color)) if first >= 1 && first <= last => {
+                Some((first..=last).map(|rank| Tile::Number(rank, color)).collect())
             }
+            _ => None,
+        };
+    }
+
+    // A group, like 5BUO, with each color at most once
+    let split = token.find(|c: char| !c.is_ascii_digit())?;
+    let (rank, letters) = token.split_at(split);
+    let mut tiles: Vec<Tile> = vec![];
+    for letter in letters.chars() {
+        let tile = parse_tile(&format!("{}{}", rank, letter))?;
+        if tiles.contains(&tile) {
+            return None;
         }
+        tiles.push(tile);
+    }
+    Some(tiles)
+}
 
-        Ok(tiles)
+/// Split a list of tiles into tokens at each character which is a
+/// separator, along with the byte offset of each token in `s`.
+pub(crate) fn tokens(s: &str, is_separator: impl Fn(char) -> bool) -> Vec<(usize, &str)> {
+    let mut res = vec![];
+    for part in s.split(is_separator) {
+        let token = part.trim();
+        if !token.is_empty() {
+            res.push((token.as_ptr() as usize - s.as_ptr() as usize, token));
+        }
     }
+    res
 }
 
 impl Display for Tiles {
     fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
         let mut is_first = true;
-        for tile in Tile::all() {
-            for _ in 0..self.get_count(&tile) {
+        for (tile, count) in self.iter() {
+            for _ in 0..count {
                 if is_first {
                     is_first = false;
                 } else {
@@ -321,10 +648,153 @@ impl Display for Tiles {
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum TilesError {
     AlreadyHaveTwo(Tile),
     InvalidTileString(String),
+    /// More copies of a tile than a `TileSet` has
+    TooMany(Tile),
+    /// A tile which isn't part of the tile set, such as `14R` in the
+    /// standard set
+    NotInTileSet(Tile),
+    /// One of the other errors, for the tile starting at byte
+    /// `offset` of a list of tiles, which is tile number `index`
+    /// counting from 0
+    At {
+        offset: usize,
+        index: usize,
+        error: Box<TilesError>,
+    },
+}
+
+impl TilesError {
+    /// The error without any position
+    pub fn inner(&self) -> &TilesError {
+        match self {
+            TilesError::At { error, .. } => error.inner(),
+            _ => self,
+        }
+    }
+
+    /// The byte offset and index of the tile with the error, if known
+    pub fn position(&self) -> Option<(usize, usize)> {
+        match self {
+            TilesError::At { offset, index, .. } => Some((*offset, *index)),
+            _ => None,
+        }
+    }
+
+    /// The same error, found in a longer list of tiles where this
+    /// list starts at byte `offset` and tile number `index`
+    pub(crate) fn shifted(self, offset: usize, index: usize) -> TilesError {
+        match self {
+            TilesError::At { offset: o, index: i, error } => TilesError::At {
+                offset: o + offset,
+                index: i + index,
+                error,
+            },
+            error => TilesError::At { offset, index, error: Box::new(error) },
+        }
+    }
+
+    /// A tile which an invalid tile string might have been meant as
+    pub fn suggestion(&self) -> Option<Tile> {
+        match self.inner() {
+            TilesError::InvalidTileString(s) => suggest(s),
+            _ => None,
+        }
+    }
+}
+
+impl Display for TilesError {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self.inner() {
+            TilesError::AlreadyHaveTwo(tile) => write!(fmt, "Already have two of {}", tile)?,
+            TilesError::InvalidTileString(s) => write!(fmt, "Invalid tile \"{}\"", s)?,
+            TilesError::TooMany(tile) => write!(fmt, "Too many of {} for the tile set", tile)?,
+            TilesError::NotInTileSet(tile) => write!(fmt, "{} is not in the tile set", tile)?,
+            TilesError::At { .. } => unreachable!(),
+        }
+        if let Some((offset, index)) = self.position() {
+            write!(fmt, " at position {} (byte {})", index + 1, offset)?;
+        }
+        if let Some(tile) = self.suggestion() {
+            write!(fmt, ", did you mean {}?", tile)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for TilesError {}
+
+/// Guess which tile was meant by a string which isn't one. Common
+/// slips are a color letter which looks like another, such as `40`
+/// for `4O`, writing the color first, or spelling the color out.
+/// Failing that, a single typo is corrected if there is only one
+/// tile it could have been.
+fn suggest(s: &str) -> Option<Tile> {
+    let s = s.to_lowercase();
+    if s.is_empty() {
+        return None;
+    }
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (rank, letters) = if split == 0 {
+        // Color first, like R4
+        let split = s.find(|c: char| c.is_ascii_digit()).unwrap_or(s.len());
+        (&s[split..], &s[..split])
+    } else if split == s.len() && (s.ends_with('0') || s.ends_with('8')) {
+        // All digits, like 40 for 4O
+        (&s[..split - 1], &s[split - 1..])
+    } else {
+        (&s[..split], &s[split..])
+    };
+    let names = [("black", 'b'), ("blue", 'u'), ("orange", 'o'), ("red", 'r'), ("yellow", 'o')];
+    let mut named = names.iter().filter(|(name, _)| letters.len() > 1 && name.starts_with(letters));
+    let color = match (letters, named.next(), named.next()) {
+        ("0", _, _) | ("q", _, _) | ("d", _, _) | ("y", _, _) => Some('o'),
+        ("8", _, _) | ("k", _, _) => Some('b'),
+        (_, Some((_, color)), None) => Some(*color),
+        _ => None,
+    };
+    if let Some(color) = color {
+        if let Ok(tile) = format!("{}{}", rank, color).parse() {
+            return Some(tile);
+        }
+    }
+    if let Ok(tile) = format!("{}{}", rank, letters).parse() {
+        return Some(tile);
+    }
+
+    let mut close = Tile::all().filter(|tile| edit_distance(&s, &tile.to_string().to_lowercase()) == 1);
+    match (close.next(), close.next()) {
+        (Some(tile), None) => Some(tile),
+        _ => None,
+    }
+}
+
+/// The number of single character insertions, deletions,
+/// substitutions and swaps of adjacent characters to turn `a` into
+/// `b`.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut d = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in d.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, cell) in d[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = if a[i - 1] == b[j - 1] { 0 } else { 1 };
+            d[i][j] = (d[i - 1][j] + 1).min(d[i][j - 1] + 1).min(d[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                d[i][j] = d[i][j].min(d[i - 2][j - 2] + 1);
+            }
+        }
+    }
+    d[a.len()][b.len()]
 }
 
 #[cfg(test)]
@@ -333,7 +803,7 @@ mod test {
 
     #[test]
     fn test_tile_count() {
-        assert_eq!(Tile::all().count(), 53)
+        assert_eq!(Tile::all().count(), 53 + SPECIAL_JOKERS as usize)
     }
 
     #[test]
@@ -386,6 +856,121 @@ mod test {
         }
     }
 
+    #[test]
+    fn test_iter() {
+        let tiles: Tiles = "1B 1B 5O 13R J J3".parse().unwrap();
+        let counts: Vec<(Tile, u8)> = tiles.iter().collect();
+        assert_eq!(
+            counts,
+            vec![
+                (Tile::Number(1, Color::Black), 2),
+                (Tile::Number(5, Color::Orange), 1),
+                (Tile::Number(13, Color::Red), 1),
+                (Tile::Joker, 1),
+                (Tile::Special(3), 1),
+            ]
+        );
+        let collected: Tiles = counts
+            .iter()
+            .flat_map(|(tile, count)| (0..*count).map(move |_| *tile))
+            .collect();
+        assert_eq!(collected, tiles);
+        assert_eq!(Tiles::new().iter().next(), None);
+        for tile in Tile::all() {
+            assert_eq!(Tile::from_index(tile.index()), tile);
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_collect_too_many() {
+        let _: Tiles = vec![Tile::Joker; 3].into_iter().collect();
+    }
+
+    /// A simple random number generator, so that tests can try many
+    /// sets of tiles and still be repeatable
+    struct XorShift(u64);
+
+    impl XorShift {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        /// Random tiles, mostly with few of them so that operations
+        /// overflow sometimes but not always
+        fn tiles(&mut self) -> Tiles {
+            let density = self.next() % 8;
+            let mut tiles = Tiles::new();
+            for tile in Tile::all() {
+                if self.next() % 8 < density {
+                    tiles.set_count(&tile, (self.next() % 2 + 1) as u8);
+                }
+            }
+            tiles
+        }
+    }
+
+    #[test]
+    fn test_multiset_algebra() {
+        use std::collections::HashMap;
+
+        fn to_map(tiles: &Tiles) -> HashMap<Tile, u8> {
+            Tile::all()
+                .map(|tile| (tile, tiles.get_count(&tile)))
+                .filter(|(_, count)| *count > 0)
+                .collect()
+        }
+        fn from_map(map: &HashMap<Tile, u8>) -> Option<Tiles> {
+            let mut tiles = Tiles::new();
+            for (tile, count) in map {
+                if *count > 2 {
+                    return None;
+                }
+                tiles.set_count(tile, *count);
+            }
+            Some(tiles)
+        }
+        fn combine(x: &HashMap<Tile, u8>, y: &HashMap<Tile, u8>, f: impl Fn(u8, u8) -> Option<u8>) -> Option<HashMap<Tile, u8>> {
+            let mut res = HashMap::new();
+            for tile in x.keys().chain(y.keys()) {
+                let count = f(*x.get(tile).unwrap_or(&0), *y.get(tile).unwrap_or(&0))?;
+                if count > 0 {
+                    res.insert(*tile, count);
+                }
+            }
+            Some(res)
+        }
+
+        let mut rng = XorShift(0x2545_f491_4f6c_dd1d);
+        for _ in 0..2000 {
+            let x = rng.tiles();
+            let y = rng.tiles();
+            let (mx, my) = (to_map(&x), to_map(&y));
+
+            let total: u8 = mx.values().sum();
+            assert_eq!(x.get_total_count(), total);
+            assert_eq!(x.is_empty(), total == 0);
+            let jokers: u8 = mx.iter().filter(|(tile, _)| tile.is_joker()).map(|(_, count)| count).sum();
+            assert_eq!(x.get_joker_count(), jokers);
+            assert_eq!(x.iter().collect::<HashMap<_, _>>(), mx);
+
+            let sum = combine(&mx, &my, |a, b| Some(a + b)).unwrap();
+            assert_eq!(x.checked_add(&y), from_map(&sum));
+            let sub = combine(&mx, &my, |a, b| a.checked_sub(b));
+            assert_eq!(x.checked_sub(&y), sub.as_ref().and_then(from_map));
+            assert_eq!(x.is_subset(&y), combine(&mx, &my, |a, b| b.checked_sub(a)).is_some());
+            let difference = combine(&mx, &my, |a, b| Some(a.saturating_sub(b))).unwrap();
+            assert_eq!(Some(x.difference(&y)), from_map(&difference));
+            let union = combine(&mx, &my, |a, b| Some(a.max(b))).unwrap();
+            assert_eq!(Some(x.union(&y)), from_map(&union));
+            let intersection = combine(&mx, &my, |a, b| Some(a.min(b))).unwrap();
+            assert_eq!(Some(x.intersection(&y)), from_map(&intersection));
+        }
+    }
+
     #[test]
     fn test_parse_empty() {
         assert_eq!("".parse::<Tiles>().unwrap(), Tiles::new());
@@ -398,6 +983,18 @@ mod test {
         assert_eq!("J".parse::<Tiles>().unwrap(), tiles);
     }
 
+    #[test]
+    fn test_parse_special_jokers() {
+        let tiles = "J2 J j1 J2".parse::<Tiles>().unwrap();
+        assert_eq!(tiles.get_count(&Tile::Special(2)), 2);
+        assert_eq!(tiles.get_joker_count(), 4);
+        assert_eq!(tiles.to_string(), "J J1 J2 J2");
+        assert_eq!(
+            "J4".parse::<Tiles>().map_err(|e| e.inner().clone()),
+            Err(TilesError::InvalidTileString(String::from("J4")))
+        );
+    }
+
     #[test]
     fn test_parse_numbers() {
         let mut tiles = Tiles::new();
@@ -411,7 +1008,11 @@ mod test {
     fn test_parse_too_many_jokers() {
         assert_eq!(
             "J j J".parse::<Tiles>(),
-            Err(TilesError::AlreadyHaveTwo(Tile::Joker))
+            Err(TilesError::At {
+                offset: 4,
+                index: 2,
+                error: Box::new(TilesError::AlreadyHaveTwo(Tile::Joker)),
+            })
         );
     }
 
@@ -427,6 +1028,131 @@ mod test {
         assert_eq!("6b 5U 6r 7o 8O".parse::<Tiles>().unwrap(), tiles);
     }
 
+    #[test]
+    fn test_parse_non_standard() {
+        assert_eq!(
+            "5G".parse::<Tiles>().map_err(|e| e.inner().clone()),
+            Err(TilesError::InvalidTileString(String::from("5G")))
+        );
+        assert_eq!(
+            "14R".parse::<Tiles>().map_err(|e| e.inner().clone()),
+            Err(TilesError::InvalidTileString(String::from("14R")))
+        );
+        assert_eq!(
+            Tiles::new().add_tile(&Tile::Number(14, Color::Red)),
+            Err(TilesError::NotInTileSet(Tile::Number(14, Color::Red)))
+        );
+        assert_eq!(parse_tile("15P", &Color::extended(), 15), Some(Tile::Number(15, Color::Purple)));
+        assert_eq!(parse_tile("16P", &Color::extended(), 15), None);
+    }
+
+    #[test]
+    fn test_count_non_standard() {
+        // These would share bits with the jokers, or not fit at all
+        let mut tiles: Tiles = "J J1 J3 1B 13R".parse().unwrap();
+        for tile in &[
+            Tile::Number(14, Color::Red),
+            Tile::Number(14, Color::Black),
+            Tile::Number(0, Color::Red),
+            Tile::Number(17, Color::Blue),
+            Tile::Number(255, Color::Blue),
+            Tile::Number(5, Color::Green),
+            Tile::Special(0),
+            Tile::Special(4),
+        ] {
+            assert_eq!(tiles.get_count(tile), 0);
+            tiles.set_count(tile, 0);
+        }
+        assert_eq!(tiles, "J J1 J3 1B 13R".parse().unwrap());
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_set_count_non_standard() {
+        Tiles::new().set_count(&Tile::Number(14, Color::Red), 1);
+    }
+
+    #[test]
+    fn test_parse_error_position() {
+        let e = "1R  2R\t14Q 3R".parse::<Tiles>().unwrap_err();
+        assert_eq!(e.position(), Some((7, 2)));
+        assert_eq!(e.inner(), &TilesError::InvalidTileString(String::from("14Q")));
+
+        let e = "1R 2R   14Q".parse::<Tiles>().unwrap_err();
+        assert_eq!(e.position(), Some((8, 2)));
+        assert_eq!(e.to_string(), "Invalid tile \"14Q\" at position 3 (byte 8)");
+        assert_eq!(
+            "J J J".parse::<Tiles>().unwrap_err().to_string(),
+            "Already have two of J at position 3 (byte 4)"
+        );
+    }
+
+    #[test]
+    fn test_parse_separators() {
+        let tiles = "1R 2R 3R".parse::<Tiles>().unwrap();
+        assert_eq!("1R,2R, 3R".parse::<Tiles>().unwrap(), tiles);
+        assert_eq!("1R\n2R\r\n3R\n".parse::<Tiles>().unwrap(), tiles);
+        assert_eq!("1R\t2R \t3R".parse::<Tiles>().unwrap(), tiles);
+        assert_eq!(" ,1R,,2R ,\n3R".parse::<Tiles>().unwrap(), tiles);
+    }
+
+    #[test]
+    fn test_parse_shorthand() {
+        fn helper(s: &str) -> String {
+            s.parse::<Tiles>().unwrap().to_string()
+        }
+        assert_eq!(helper("3-7R"), "3R 4R 5R 6R 7R");
+        assert_eq!(helper("12-13b 5-5u"), "5U 12B 13B");
+        assert_eq!(helper("5BUO"), "5B 5U 5O");
+        assert_eq!(helper("13rbuo"), "13B 13U 13O 13R");
+        assert_eq!(helper("2x4O 1xJ"), "4O 4O J");
+        assert_eq!(helper("2x1-3R, 2X5BU"), "1R 1R 2R 2R 3R 3R 5B 5B 5U 5U");
+
+        for s in &["7-3R", "0-3R", "3-14R", "-3R", "3-R", "3-J", "5BB", "5BUX", "0x4O", "x4O", "3x", "2x2x"] {
+            assert_eq!(
+                s.parse::<Tiles>().map_err(|e| e.inner().clone()),
+                Err(TilesError::InvalidTileString(s.to_string())),
+                "{}",
+                s
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_shorthand_too_many() {
+        assert_eq!(
+            "3x4O".parse::<Tiles>(),
+            Err(TilesError::At {
+                offset: 0,
+                index: 2,
+                error: Box::new(TilesError::AlreadyHaveTwo(Tile::Number(4, Color::Orange))),
+            })
+        );
+        let e = "5R 5O 5O, 1-5R 5BUO".parse::<Tiles>().unwrap_err();
+        assert_eq!(e.inner(), &TilesError::AlreadyHaveTwo(Tile::Number(5, Color::Orange)));
+        assert_eq!(e.position(), Some((15, 10)));
+        assert_eq!(e.to_string(), "Already have two of 5O at position 11 (byte 15)");
+    }
+
+    #[test]
+    fn test_suggestions() {
+        fn helper(s: &str) -> Option<String> {
+            s.parse::<Tiles>().unwrap_err().suggestion().map(|tile| tile.to_string())
+        }
+        assert_eq!(helper("40"), Some(String::from("4O")));
+        assert_eq!(helper("4Q"), Some(String::from("4O")));
+        assert_eq!(helper("R12"), Some(String::from("12R")));
+        assert_eq!(helper("7blue"), Some(String::from("7U")));
+        assert_eq!(helper("7bla"), Some(String::from("7B")));
+        assert_eq!(helper("5RR"), Some(String::from("5R")));
+        assert_eq!(helper("14R"), None);
+        assert_eq!(helper("xyz"), None);
+        assert_eq!(
+            "1R 40".parse::<Tiles>().unwrap_err().to_string(),
+            "Invalid tile \"40\" at position 2 (byte 3), did you mean 4O?"
+        );
+    }
+
     #[test]
     fn test_parse_display() {
         fn helper(tiles: &Tiles) {
@@ -0,0 +1,378 @@
+use std::fmt::Display;
+use super::tiles::*;
+
+/// A description of the tiles in a Rummikub edition: how many colors,
+/// ranks running from 1 up to `ranks`, how many copies of each
+/// numbered tile and how many jokers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TileSet {
+    colors: u8,
+    ranks: u8,
+    copies: u8,
+    jokers: u8,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum TileSetError {
+    /// Between 1 and 6 colors are supported
+    InvalidColors(u8),
+    /// There has to be at least one rank
+    InvalidRanks(u8),
+    /// There has to be at least one copy of each tile
+    InvalidCopies(u8),
+}
+
+impl TileSet {
+    /// The standard set: 4 colors, ranks 1 to 13, 2 copies of each
+    /// and 2 jokers
+    pub fn standard() -> TileSet {
+        TileSet {
+            colors: 4,
+            ranks: 13,
+            copies: 2,
+            jokers: 2,
+        }
+    }
+
+    pub fn new(colors: u8, ranks: u8, copies: u8, jokers: u8) -> Result<TileSet, TileSetError> {
+        if colors < 1 || colors as usize > Color::extended().len() {
+            return Err(TileSetError::InvalidColors(colors));
+        }
+        if ranks < 1 {
+            return Err(TileSetError::InvalidRanks(ranks));
+        }
+        if copies < 1 {
+            return Err(TileSetError::InvalidCopies(copies));
+        }
+        Ok(TileSet {
+            colors,
+            ranks,
+            copies,
+            jokers,
+        })
+    }
+
+    /// Can sets of these tiles be stored in `Tiles`?
+    pub fn is_standard(&self) -> bool {
+        *self == TileSet::standard()
+    }
+
+    pub fn colors(&self) -> Vec<Color> {
+        Color::extended()[..self.colors as usize].to_vec()
+    }
+
+    pub fn ranks(&self) -> u8 {
+        self.ranks
+    }
+
+    /// All of the different tiles, in the same order as `Tile::all`
+    /// for the standard set. Only the standard set has special jokers.
+    pub fn tiles(&self) -> Vec<Tile> {
+        if self.is_standard() {
+            return Tile::all().collect();
+        }
+        let mut tiles = vec![];
+        for rank in 1..=self.ranks {
+            for color in self.colors() {
+                tiles.push(Tile::Number(rank, color));
+            }
+        }
+        if self.jokers > 0 {
+            tiles.push(Tile::Joker);
+        }
+        tiles
+    }
+
+    pub fn contains(&self, tile: &Tile) -> bool {
+        match tile {
+            Tile::Special(_) => self.is_standard() && tile.is_standard(),
+            _ => self.index(tile).is_some(),
+        }
+    }
+
+    /// The number of copies of the given tile in the full set
+    pub fn max_count(&self, tile: &Tile) -> u8 {
+        match tile {
+            _ if !self.contains(tile) => 0,
+            Tile::Joker => self.jokers,
+            Tile::Number(..) | Tile::Special(_) => self.copies,
+        }
+    }
+
+    /// Where the given tile comes in `tiles`
+    fn index(&self, tile: &Tile) -> Option<usize> {
+        match tile {
+            Tile::Joker if self.jokers > 0 => Some(self.ranks as usize * self.colors as usize),
+            // Special jokers are only in the standard set, which is
+            // kept in `Tiles` and has no need for an index
+            Tile::Joker | Tile::Special(_) => None,
+            Tile::Number(rank, color) => {
+                let color = Color::extended()[..self.colors as usize]
+                    .iter()
+                    .position(|c| c == color)?;
+                if *rank < 1 || *rank > self.ranks {
+                    return None;
+                }
+                Some((*rank as usize - 1) * self.colors as usize + color)
+            }
+        }
+    }
+
+    /// The number of bits needed for the count of each tile, rounded
+    /// up so that counts never straddle two words
+    fn bits(&self) -> usize {
+        let max = self.copies.max(self.jokers);
+        match 8 - max.leading_zeros() {
+            0 | 1 => 1,
+            2 => 2,
+            3 | 4 => 4,
+            _ => 8,
+        }
+    }
+
+    /// Parse a single tile from this set
+    pub fn parse_tile(&self, s: &str) -> Result<Tile, TilesError> {
+        match parse_tile(s, &self.colors(), self.ranks) {
+            Some(tile) if tile.is_joker() && !self.contains(&tile) => Err(TilesError::NotInTileSet(tile)),
+            Some(tile) => Ok(tile),
+            None => Err(TilesError::InvalidTileString(String::from(s))),
+        }
+    }
+
+    /// Parse a whitespace separated list of tiles from this set. Like
+    /// parsing `Tiles`, errors say which tile was wrong.
+    pub fn parse(&self, s: &str) -> Result<TileBag, TilesError> {
+        let mut bag = TileBag::new(*self);
+        for (index, (offset, token)) in tokens(s, char::is_whitespace).into_iter().enumerate() {
+            let at = |error| TilesError::At { offset, index, error: Box::new(error) };
+            let tile = self.parse_tile(token).map_err(at)?;
+            bag.add_tile(&tile).map_err(at)?;
+        }
+        Ok(bag)
+    }
+}
+
+impl Default for TileSet {
+    fn default() -> TileSet {
+        TileSet::standard()
+    }
+}
+
+/// A multiset of tiles from any `TileSet`, like `Tiles` but without
+/// the limit of 128 bits. Sets of standard tiles, special jokers
+/// included, are kept in a `Tiles`, so that they can be handed to the
+/// solver as they are.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct TileBag {
+    set: TileSet,
+    counts: Counts,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Counts {
+    Standard(Tiles),
+    /// The count of each tile in `TileSet::bits` bits, in the order
+    /// of `TileSet::tiles`
+    Packed(Vec<u64>),
+}
+
+impl TileBag {
+    /// An empty set of tiles
+    pub fn new(set: TileSet) -> TileBag {
+        let counts = if set.is_standard() {
+            Counts::Standard(Tiles::new())
+        } else {
+            // Room for a count past the last tile, which keeps this simple
+            // when the counts fill the last word exactly
+            let bits = set.tiles().len() * set.bits();
+            Counts::Packed(vec![0; bits / 64 + 1])
+        };
+        TileBag { set, counts }
+    }
+
+    pub fn tile_set(&self) -> &TileSet {
+        &self.set
+    }
+
+    /// How many of this kind of tile do we have? Always 0 for tiles
+    /// which aren't in the set.
+    pub fn get_count(&self, tile: &Tile) -> u8 {
+        match &self.counts {
+            Counts::Standard(tiles) if tile.is_standard() => tiles.get_count(tile),
+            Counts::Standard(_) => 0,
+            Counts::Packed(words) => match self.set.index(tile) {
+                None => 0,
+                Some(index) => {
+                    let bits = self.set.bits();
+                    let (word, shift) = (index * bits / 64, index * bits % 64);
+                    ((words[word] >> shift) & ((1 << bits) - 1)) as u8
+                }
+            },
+        }
+    }
+
+    /// Set the number of tiles of this kind. This will panic if the
+    /// tile isn't in the set, or there aren't that many copies of it.
+    pub fn set_count(&mut self, tile: &Tile, count: u8) {
+        if count > self.set.max_count(tile) {
+            panic!("Cannot have {} of {} in this tile set", count, tile);
+        }
+        let bits = self.set.bits();
+        match &mut self.counts {
+            Counts::Standard(tiles) => tiles.set_count(tile, count),
+            Counts::Packed(words) => {
+                let index = self.set.index(tile).unwrap();
+                let (word, shift) = (index * bits / 64, index * bits % 64);
+                let mask = ((1u64 << bits) - 1) << shift;
+                words[word] = (words[word] & !mask) | (u64::from(count) << shift);
+            }
+        }
+    }
+
+    /// Add another tile of the given type. May fail if we already
+    /// have every copy of it, or it isn't part of the set.
+    pub fn add_tile(&mut self, tile: &Tile) -> Result<(), TilesError> {
+        if !self.set.contains(tile) {
+            return Err(TilesError::NotInTileSet(*tile));
+        }
+        let count = self.get_count(tile);
+        if count >= self.set.max_count(tile) {
+            return Err(match self.counts {
+                Counts::Standard(_) => TilesError::AlreadyHaveTwo(*tile),
+                Counts::Packed(_) => TilesError::TooMany(*tile),
+            });
+        }
+        self.set_count(tile, count + 1);
+        Ok(())
+    }
+
+    /// How many tiles total do we have?
+    pub fn get_total_count(&self) -> u32 {
+        self.set
+            .tiles()
+            .iter()
+            .map(|tile| u32::from(self.get_count(tile)))
+            .sum()
+    }
+
+    /// The same tiles as `Tiles`, if they come from the standard set
+    pub fn as_standard(&self) -> Option<Tiles> {
+        match self.counts {
+            Counts::Standard(tiles) => Some(tiles),
+            Counts::Packed(_) => None,
+        }
+    }
+}
+
+impl From<Tiles> for TileBag {
+    fn from(tiles: Tiles) -> TileBag {
+        TileBag {
+            set: TileSet::standard(),
+            counts: Counts::Standard(tiles),
+        }
+    }
+}
+
+impl Display for TileBag {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let mut is_first = true;
+        for tile in self.set.tiles() {
+            for _ in 0..self.get_count(&tile) {
+                if is_first {
+                    is_first = false;
+                } else {
+                    write!(fmt, " ")?;
+                }
+                tile.fmt(fmt)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Six colors, ranks up to 15, three copies and four jokers
+    fn big_set() -> TileSet {
+        TileSet::new(6, 15, 3, 4).unwrap()
+    }
+
+    #[test]
+    fn test_standard_set() {
+        let set = TileSet::standard();
+        assert!(set.is_standard());
+        assert_eq!(set.tiles(), Tile::all().collect::<Vec<_>>());
+        let bag = set.parse("1R 2R 3R J").unwrap();
+        assert_eq!(bag.as_standard(), Some("1R 2R 3R J".parse().unwrap()));
+        assert_eq!(set.parse("1R\t2R\n3R  J").unwrap(), bag);
+        assert_eq!(TileBag::from(bag.as_standard().unwrap()), bag);
+    }
+
+    #[test]
+    fn test_standard_set_special_jokers() {
+        let set = TileSet::standard();
+        let mut bag = set.parse("J2 1R J1 J2").unwrap();
+        assert_eq!(bag.as_standard(), Some("J2 1R J1 J2".parse().unwrap()));
+        assert_eq!(bag.get_count(&Tile::Special(2)), 2);
+        assert_eq!(bag.get_total_count(), 4);
+        assert_eq!(bag.to_string(), "1R J1 J2 J2");
+        assert_eq!(bag.add_tile(&Tile::Special(2)), Err(TilesError::AlreadyHaveTwo(Tile::Special(2))));
+        assert_eq!(bag.add_tile(&Tile::Special(4)), Err(TilesError::NotInTileSet(Tile::Special(4))));
+        assert_eq!(big_set().parse("J1").unwrap_err().inner(), &TilesError::NotInTileSet(Tile::Special(1)));
+        assert_eq!(TileBag::new(big_set()).get_count(&Tile::Special(1)), 0);
+    }
+
+    #[test]
+    fn test_invalid_set() {
+        assert_eq!(TileSet::new(7, 13, 2, 2), Err(TileSetError::InvalidColors(7)));
+        assert_eq!(TileSet::new(4, 0, 2, 2), Err(TileSetError::InvalidRanks(0)));
+        assert_eq!(TileSet::new(4, 13, 0, 2), Err(TileSetError::InvalidCopies(0)));
+    }
+
+    #[test]
+    fn test_big_set_counts() {
+        let set = big_set();
+        assert!(!set.is_standard());
+        assert_eq!(set.tiles().len(), 6 * 15 + 1);
+
+        let mut bag = TileBag::new(set);
+        for tile in set.tiles() {
+            for _ in 0..set.max_count(&tile) {
+                bag.add_tile(&tile).unwrap();
+            }
+            assert_eq!(bag.add_tile(&tile), Err(TilesError::TooMany(tile)));
+        }
+        assert_eq!(bag.get_total_count(), 6 * 15 * 3 + 4);
+        assert_eq!(bag.as_standard(), None);
+
+        bag.set_count(&Tile::Number(15, Color::Purple), 1);
+        assert_eq!(bag.get_count(&Tile::Number(15, Color::Purple)), 1);
+        assert_eq!(bag.get_count(&Tile::Number(14, Color::Purple)), 3);
+        assert_eq!(bag.get_count(&Tile::Joker), 4);
+    }
+
+    #[test]
+    fn test_big_set_parse() {
+        let set = big_set();
+        let bag = set.parse("15P 14G 1R J J J J 15P 15P").unwrap();
+        assert_eq!(bag.to_string(), "1R 14G 15P 15P 15P J J J J");
+        assert_eq!(set.parse(&bag.to_string()), Ok(bag));
+        assert_eq!(set.parse("16R").unwrap_err().inner(), &TilesError::InvalidTileString(String::from("16R")));
+        let e = set.parse("5R 5R 5R 5R").unwrap_err();
+        assert_eq!(e.inner(), &TilesError::TooMany(Tile::Number(5, Color::Red)));
+        assert_eq!(e.position(), Some((9, 3)));
+    }
+
+    #[test]
+    fn test_small_set() {
+        let set = TileSet::new(2, 5, 1, 0).unwrap();
+        assert_eq!(set.colors(), vec![Color::Black, Color::Blue]);
+        assert_eq!(set.parse("J").unwrap_err().inner(), &TilesError::NotInTileSet(Tile::Joker));
+        assert_eq!(set.parse("1O").unwrap_err().inner(), &TilesError::InvalidTileString(String::from("1O")));
+        let mut bag = TileBag::new(set);
+        assert_eq!(bag.add_tile(&Tile::Number(6, Color::Black)), Err(TilesError::NotInTileSet(Tile::Number(6, Color::Black))));
+        assert_eq!(bag.get_count(&Tile::Number(6, Color::Black)), 0);
+    }
+}
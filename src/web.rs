@@ -6,7 +6,13 @@ use std::rc::Rc;
 use std::cell::RefCell;
 
 use super::tiles::{Tiles, Color, Tile};
-use super::solve::{solve};
+use super::solve::{solve_with, unplaceable_tiles_with_options, SolveOptions};
+use super::board::Board;
+
+/// Keep the page responsive on pathological boards. `Instant` panics
+/// on wasm32, so limit the number of search states instead of time.
+const MAX_SOLUTIONS: usize = 500;
+const MAX_NODES: u64 = 2_000_000;
 
 pub fn main() {
     build().unwrap();
@@ -40,6 +46,9 @@ td.rank {
     width: 1.5em;
     text-align: center;
 }
+.tile.joker {
+    width: auto;
+}
 .tile.red {
     color: red;
 }
@@ -49,6 +58,12 @@ td.rank {
 .tile.blue {
     color: blue;
 }
+.tile.green {
+    color: green;
+}
+.tile.purple {
+    color: purple;
+}
 .combo {
     margin-right: 1em;
     display: inline-block;
@@ -114,6 +129,9 @@ ul.solutions > li {
     table.append_child(&tbody);
 
     let tiles = Rc::new(RefCell::new(Tiles::new()));
+    // The first solution shown last time, which is what the player
+    // most likely laid out on the table
+    let layout = Rc::new(RefCell::new(Board::new()));
 
     let mut make_buttons: impl FnMut(&Document, &Element, Tile) -> Result<(), InvalidCharacterError> = move |doc: &Document, td: &Element, tile: Tile| {
         let button_group = doc.create_element("div")?;
@@ -136,6 +154,7 @@ ul.solutions > li {
 
             let buttons = buttons.clone();
             let tiles = tiles.clone();
+            let layout = layout.clone();
             let solution_ul = solution_ul.clone();
             let callback = move || {
                 let doc = document();
@@ -153,22 +172,59 @@ ul.solutions > li {
 
                 let mut tiles = tiles.borrow_mut();
                 tiles.set_count(&tile, count);
-                let solutions = solve(*tiles);
+                let options = SolveOptions {
+                    max_solutions: Some(MAX_SOLUTIONS),
+                    max_nodes: Some(MAX_NODES),
+                    ..SolveOptions::default()
+                };
+                let result = solve_with(*tiles, &options);
+
+                if let Some(reason) = result.stopped {
+                    let li = doc.create_element("li")?;
+                    li.set_attribute("class", "text-muted")?;
+                    let msg = format!("Search stopped early ({}), showing the first {} solutions", reason, result.solutions.len());
+                    li.append_child(&doc.create_text_node(&msg));
+                    solution_ul.append_child(&li);
+                }
+
+                // Finding the tiles which cannot be placed is a search
+                // of its own, so it gets the same budget.
+                if result.solutions.is_empty() && result.is_exhaustive() {
+                    let li = doc.create_element("li")?;
+                    li.set_attribute("class", "text-danger")?;
+                    let msg = match unplaceable_tiles_with_options(*tiles, &options) {
+                        Ok(unplaceable) => format!("No solutions, these tiles cannot be placed: {}", unplaceable),
+                        Err(reason) => format!("No solutions, and the search for the tiles which cannot be placed stopped early ({})", reason),
+                    };
+                    li.append_child(&doc.create_text_node(&msg));
+                    solution_ul.append_child(&li);
+                }
+
+                // List the arrangements which disturb the previous
+                // layout least first. Any newly added tiles can come
+                // from the "rack".
+                let mut layout = layout.borrow_mut();
+                let solutions = layout.sort_by_stability(result.solutions, *tiles);
+                if let Some(first) = solutions.first() {
+                    layout.melds = first.combos.clone();
+                }
 
                 for sol in solutions.into_iter() {
                     let li = doc.create_element("li")?;
                     solution_ul.append_child(&li);
 
-                    let on_combo = |combo: &Tiles| {
+                    let on_combo = |layout: &[(Tile, bool)]| {
                         let span = doc.create_element("span")?;
                         span.set_attribute("class", "combo")?;
                         li.append_child(&span);
 
-                        for tile in Tile::all() {
-                            let count = combo.get_count(&tile);
-                            for _ in 0..count {
-                                span.append_child(&tile_span(&doc, &tile)?);
-                            }
+                        for (tile, is_joker) in layout {
+                            let tile_span = if *is_joker {
+                                joker_span(&doc, tile)?
+                            } else {
+                                tile_span(&doc, tile)?
+                            };
+                            span.append_child(&tile_span);
                         }
 
                         let res: Result<(), InvalidCharacterError> = Ok(());
@@ -176,13 +232,12 @@ ul.solutions > li {
                     };
 
                     for combo in sol.combos.iter().rev() {
-                        on_combo(combo)?;
+                        on_combo(&combo.layout())?;
                     }
 
                     if sol.leftover_jokers > 0 {
-                        let mut tiles = Tiles::new();
-                        tiles.set_count(&Tile::Joker, sol.leftover_jokers);
-                        on_combo(&tiles)?;
+                        let jokers = vec![(Tile::Joker, false); sol.leftover_jokers as usize];
+                        on_combo(&jokers)?;
                     }
                 }
 
@@ -234,23 +289,38 @@ ul.solutions > li {
     Ok(())
 }
 
+fn tile_class(tile: &Tile) -> &'static str {
+    match tile {
+        Tile::Joker | Tile::Special(_) => "tile",
+        Tile::Number(_, color) => match color {
+            Color::Black => "tile black",
+            Color::Blue => "tile blue",
+            Color::Orange => "tile orange",
+            Color::Red => "tile red",
+            Color::Green => "tile green",
+            Color::Purple => "tile purple",
+        },
+    }
+}
+
 fn tile_span(doc: &Document, tile: &Tile) -> Result<Element, InvalidCharacterError> {
     let span = doc.create_element("span")?;
+    span.set_attribute("class", tile_class(tile))?;
     match tile {
-        Tile::Joker => {
-            span.set_attribute("class", "tile")?;
-            span.append_child(&doc.create_text_node("J"));
-        }
-        Tile::Number(rank, color) => {
-            let class = match color {
-                Color::Black => "tile black",
-                Color::Blue => "tile blue",
-                Color::Orange => "tile orange",
-                Color::Red => "tile red",
-            };
-            span.set_attribute("class", class)?;
-            span.append_child(&doc.create_text_node(&rank.to_string()));
-        }
+        Tile::Joker | Tile::Special(_) => span.append_child(&doc.create_text_node(&tile.to_string())),
+        Tile::Number(rank, _) => span.append_child(&doc.create_text_node(&rank.to_string())),
     }
     Ok(span)
 }
+
+/// Display a joker along with the tile it stands in for
+fn joker_span(doc: &Document, tile: &Tile) -> Result<Element, InvalidCharacterError> {
+    let span = doc.create_element("span")?;
+    span.set_attribute("class", &format!("{} joker", tile_class(tile)))?;
+    span.set_attribute("title", &format!("J={}", tile))?;
+    span.append_child(&doc.create_text_node("J"));
+    let sub = doc.create_element("sub")?;
+    sub.append_child(&doc.create_text_node(&tile.to_string()));
+    span.append_child(&sub);
+    Ok(span)
+}